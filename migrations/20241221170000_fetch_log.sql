@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS fetch_log (
+  id serial PRIMARY KEY,
+  feed_id int NOT NULL REFERENCES raw_feeds(id) ON DELETE CASCADE,
+  started_at timestamptz NOT NULL,
+  duration_ms int NOT NULL,
+  http_status int,
+  bytes int,
+  entries_parsed int,
+  new_entries int,
+  error text
+);
+
+CREATE INDEX fetch_log_feed_id_started_at_idx ON fetch_log(feed_id, started_at DESC);
+CREATE INDEX fetch_log_started_at_idx ON fetch_log(started_at);
@@ -0,0 +1 @@
+ALTER TABLE fetch_log ADD COLUMN IF NOT EXISTS error_kind varchar;
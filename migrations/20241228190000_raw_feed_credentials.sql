@@ -0,0 +1,2 @@
+-- Encrypted per-feed request headers and basic auth (nonce || AES-256-GCM ciphertext)
+ALTER TABLE raw_feeds ADD COLUMN IF NOT EXISTS credentials bytea;
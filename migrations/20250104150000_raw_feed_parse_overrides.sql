@@ -0,0 +1 @@
+ALTER TABLE raw_feeds ADD COLUMN IF NOT EXISTS parse_overrides jsonb NOT NULL DEFAULT '{}';
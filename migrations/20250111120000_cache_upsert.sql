@@ -0,0 +1,4 @@
+-- Cached feeds now persist across refreshes; freshness is tracked separately
+-- from creation time. A NULL last_refreshed_at marks the feed as expired.
+ALTER TABLE cached_feeds ADD COLUMN IF NOT EXISTS last_refreshed_at timestamptz DEFAULT CURRENT_TIMESTAMP;
+UPDATE cached_feeds SET last_refreshed_at = created_date;
@@ -0,0 +1,11 @@
+-- A rule with neither feed_id nor category_id is the global default
+CREATE TABLE IF NOT EXISTS retention_policies (
+  id serial PRIMARY KEY,
+  feed_id int UNIQUE REFERENCES raw_feeds(id) ON DELETE CASCADE,
+  category_id int UNIQUE REFERENCES categories(id) ON DELETE CASCADE,
+  policy jsonb NOT NULL,
+  CHECK (num_nonnulls(feed_id, category_id) <= 1)
+);
+
+CREATE UNIQUE INDEX retention_policies_global_idx ON retention_policies ((TRUE))
+WHERE feed_id IS NULL AND category_id IS NULL;
@@ -0,0 +1,6 @@
+-- Per-feed freshness: when the feed was last attempted and why that attempt failed.
+-- last_refreshed_at only moves on a successful refresh, so failing feeds keep
+-- serving their last good entries.
+ALTER TABLE cached_feeds ADD COLUMN IF NOT EXISTS last_attempt_at timestamptz;
+ALTER TABLE cached_feeds ADD COLUMN IF NOT EXISTS last_error text;
+UPDATE cached_feeds SET last_attempt_at = last_refreshed_at;
@@ -0,0 +1,3 @@
+-- Lets /feeds read the newest entries of every feed straight off the index
+CREATE INDEX IF NOT EXISTS cached_entries_feed_id_created_date_idx
+  ON cached_entries(feed_id, created_date DESC);
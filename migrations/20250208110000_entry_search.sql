@@ -0,0 +1,10 @@
+-- Entry bodies are stored as published (usually HTML) and indexed without their tags
+ALTER TABLE cached_entries ADD COLUMN IF NOT EXISTS content text;
+
+ALTER TABLE cached_entries ADD COLUMN IF NOT EXISTS search tsvector
+  GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', title), 'A') ||
+    setweight(to_tsvector('english', coalesce(regexp_replace(content, '<[^>]*>', ' ', 'g'), '')), 'B')
+  ) STORED;
+
+CREATE INDEX IF NOT EXISTS cached_entries_search_idx ON cached_entries USING GIN (search);
@@ -0,0 +1,31 @@
+CREATE TABLE IF NOT EXISTS users (
+  id serial PRIMARY KEY,
+  github_id bigint NOT NULL UNIQUE,
+  created_date timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Everything published up to read_until counts as read. A marker with neither
+-- feed_id nor category_id covers every feed of the user.
+CREATE TABLE IF NOT EXISTS read_markers (
+  id serial PRIMARY KEY,
+  user_id int NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  feed_id int REFERENCES raw_feeds(id) ON DELETE CASCADE,
+  category_id int REFERENCES categories(id) ON DELETE CASCADE,
+  read_until timestamptz NOT NULL,
+  CHECK (num_nonnulls(feed_id, category_id) <= 1)
+);
+
+CREATE UNIQUE INDEX read_markers_feed_idx ON read_markers(user_id, feed_id)
+WHERE feed_id IS NOT NULL;
+CREATE UNIQUE INDEX read_markers_category_idx ON read_markers(user_id, category_id)
+WHERE category_id IS NOT NULL;
+CREATE UNIQUE INDEX read_markers_all_idx ON read_markers(user_id)
+WHERE feed_id IS NULL AND category_id IS NULL;
+
+-- Entries read one by one, on top of any marker
+CREATE TABLE IF NOT EXISTS entry_reads (
+  user_id int NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  entry_id int NOT NULL REFERENCES cached_entries(id) ON DELETE CASCADE,
+  read_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (user_id, entry_id)
+);
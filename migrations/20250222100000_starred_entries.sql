@@ -0,0 +1,10 @@
+-- Starred entries are never pruned. Their cached feed is kept around as well,
+-- even after the raw feed is removed, so the entries have somewhere to live.
+CREATE TABLE IF NOT EXISTS starred_entries (
+  user_id int NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  entry_id int NOT NULL REFERENCES cached_entries(id) ON DELETE CASCADE,
+  starred_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (user_id, entry_id)
+);
+
+CREATE INDEX starred_entries_entry_id_idx ON starred_entries(entry_id);
@@ -0,0 +1,26 @@
+-- raw_feeds is the shared catalog, fetched once per URL. Users subscribe to
+-- catalog feeds and file them under categories of their own.
+CREATE TABLE IF NOT EXISTS subscriptions (
+  id serial PRIMARY KEY,
+  user_id int NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  feed_id int NOT NULL REFERENCES raw_feeds(id) ON DELETE CASCADE,
+  category varchar NOT NULL,
+  created_date timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (user_id, feed_id)
+);
+
+CREATE INDEX subscriptions_feed_id_idx ON subscriptions(feed_id);
+
+-- Category read markers follow the user's own categories from now on
+ALTER TABLE read_markers ADD COLUMN IF NOT EXISTS category varchar;
+UPDATE read_markers m SET category = c.name FROM categories c WHERE c.id = m.category_id;
+
+DROP INDEX IF EXISTS read_markers_category_idx;
+DROP INDEX IF EXISTS read_markers_all_idx;
+ALTER TABLE read_markers DROP COLUMN category_id;
+ALTER TABLE read_markers ADD CHECK (num_nonnulls(feed_id, category) <= 1);
+
+CREATE UNIQUE INDEX read_markers_category_idx ON read_markers(user_id, category)
+WHERE category IS NOT NULL;
+CREATE UNIQUE INDEX read_markers_all_idx ON read_markers(user_id)
+WHERE feed_id IS NULL AND category IS NULL;
@@ -0,0 +1,25 @@
+-- A feed can carry any number of tags. Its category stays as its primary
+-- grouping for /categories and is always one of its tags.
+CREATE TABLE IF NOT EXISTS tags (
+  id serial PRIMARY KEY,
+  name varchar NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS raw_feed_tags (
+  feed_id int NOT NULL REFERENCES raw_feeds(id) ON DELETE CASCADE,
+  tag_id int NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+  PRIMARY KEY (feed_id, tag_id)
+);
+
+CREATE INDEX raw_feed_tags_tag_id_idx ON raw_feed_tags(tag_id);
+
+INSERT INTO tags (name)
+SELECT name FROM categories
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO raw_feed_tags (feed_id, tag_id)
+SELECT r.id, t.id
+FROM raw_feeds r
+JOIN categories c ON c.id = r.category_id
+JOIN tags t ON t.name = c.name
+ON CONFLICT DO NOTHING;
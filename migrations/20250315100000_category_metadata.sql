@@ -0,0 +1,18 @@
+-- Display metadata for categories. Categories created or edited through the
+-- admin API are managed and stay around even while no feed uses them.
+ALTER TABLE categories ADD COLUMN IF NOT EXISTS description text;
+ALTER TABLE categories ADD COLUMN IF NOT EXISTS color varchar;
+ALTER TABLE categories ADD COLUMN IF NOT EXISTS icon varchar;
+ALTER TABLE categories ADD COLUMN IF NOT EXISTS position int NOT NULL DEFAULT 0;
+ALTER TABLE categories ADD COLUMN IF NOT EXISTS managed boolean NOT NULL DEFAULT false;
+
+CREATE OR REPLACE FUNCTION delete_orphaned_categories()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM categories
+    WHERE NOT managed
+        AND id NOT IN (SELECT category_id FROM raw_feeds)
+        AND id NOT IN (SELECT category_id FROM cached_feeds);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,4 @@
+-- Inactive feeds, and feeds paused until a point in time, keep their
+-- configuration but are neither refreshed nor served.
+ALTER TABLE raw_feeds ADD COLUMN IF NOT EXISTS active boolean NOT NULL DEFAULT TRUE;
+ALTER TABLE raw_feeds ADD COLUMN IF NOT EXISTS paused_until timestamptz;
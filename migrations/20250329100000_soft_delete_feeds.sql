@@ -0,0 +1,3 @@
+-- Deleted feeds stay around, hidden, until the purge job removes them after
+-- a grace period. Their name and URL stay taken until then.
+ALTER TABLE raw_feeds ADD COLUMN IF NOT EXISTS deleted_at timestamptz;
@@ -0,0 +1,10 @@
+-- Every mutating admin call, with the fields it changed before and after
+CREATE TABLE IF NOT EXISTS audit_log (
+  id bigserial PRIMARY KEY,
+  github_id bigint NOT NULL,
+  action varchar NOT NULL,
+  target_id int,
+  before jsonb,
+  after jsonb,
+  created_date timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,17 @@
+-- Tags only exist while a feed carries them, like categories without metadata.
+CREATE OR REPLACE FUNCTION delete_orphaned_tags()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM tags
+    WHERE NOT EXISTS (SELECT 1 FROM raw_feed_tags WHERE raw_feed_tags.tag_id = tags.id);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trigger_delete_orphaned_tags
+AFTER DELETE ON raw_feed_tags
+FOR EACH STATEMENT
+EXECUTE FUNCTION delete_orphaned_tags();
+
+DELETE FROM tags
+WHERE NOT EXISTS (SELECT 1 FROM raw_feed_tags WHERE raw_feed_tags.tag_id = tags.id);
@@ -0,0 +1,8 @@
+-- Whether a feed is fetched and served: not deleted, active and not paused.
+-- Mirrors RawFeed::is_enabled.
+CREATE OR REPLACE FUNCTION feed_enabled(feed raw_feeds)
+RETURNS boolean AS $$
+    SELECT feed.deleted_at IS NULL
+        AND feed.active
+        AND (feed.paused_until IS NULL OR feed.paused_until <= NOW());
+$$ LANGUAGE sql STABLE;
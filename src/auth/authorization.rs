@@ -1,15 +1,20 @@
 use anyhow::Context;
 use axum::{
     extract::{Request, State},
+    http::HeaderMap,
     middleware::Next,
     response::IntoResponse,
 };
-use chrono::{Duration, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use reqwest::{Client, StatusCode};
 use serde::Deserialize;
 use shuttle_runtime::SecretStore;
 
-use crate::{error::ServiceError, AppState};
+use crate::{
+    data::{Admin, UserDataSource},
+    error::ServiceError,
+    AppState,
+};
 
 #[derive(Debug, Deserialize)]
 struct AuthenticatedUser {
@@ -66,10 +71,11 @@ async fn invalidate_expired_token(
     Ok(())
 }
 
+/// Returns the GitHub user id the token belongs to and when the token expires.
 async fn fetch_github_user_id(
     secrets: &SecretStore,
     access_token: &str,
-) -> Result<String, ServiceError> {
+) -> Result<(String, DateTime<Utc>), ServiceError> {
     let github_client_id = SecretStore::get(secrets, "GITHUB_CLIENT_ID")
         .context("Missing expected ENV_VAR: GITHUB_CLIENT_ID")?;
     let github_client_secret = SecretStore::get(secrets, "GITHUB_CLIENT_SECRET")
@@ -120,43 +126,94 @@ async fn fetch_github_user_id(
         })
         .context("Failed to generate user token")?;
 
-    if token_created + Duration::hours(1) < now.naive_utc() {
+    let expires_at = (token_created + Duration::hours(1)).and_utc();
+    if expires_at < now {
         invalidate_expired_token(secrets, access_token).await?;
         return Err(ServiceError::from(anyhow::Error::msg(
             "Expired access token! Token invalidated...",
         )));
     }
 
-    Ok(token_info.user.id.to_string())
+    Ok((token_info.user.id.to_string(), expires_at))
 }
 
-pub async fn auth_middleware(
-    State(state): State<AppState>,
-    req: Request,
-    next: Next,
-) -> Result<impl IntoResponse, ServiceError> {
-    let (parts, body) = req.into_parts();
-    let headers = parts.headers.clone();
-
+async fn authenticate(state: &AppState, headers: &HeaderMap) -> Result<String, ServiceError> {
     let bearer_token = headers
         .get("Authorization")
         .and_then(|value| value.to_str().ok())
         .context("Missing or invalid Authorization header")?;
 
     let access_token = bearer_token.replace("Bearer ", "");
-    let user_id = fetch_github_user_id(&state.secrets, &access_token).await?;
+    if let Some(github_id) = state.tokens.get(&access_token) {
+        return Ok(github_id);
+    }
 
-    let admin_user_id = SecretStore::get(&state.secrets, "GITHUB_USER_ID")
+    let (github_id, expires_at) = fetch_github_user_id(&state.secrets, &access_token).await?;
+    state.tokens.insert(&access_token, &github_id, expires_at);
+    Ok(github_id)
+}
+
+/// Returns the `Admin` if `github_id` is the configured admin.
+pub fn as_admin(secrets: &SecretStore, github_id: i64) -> Result<Option<Admin>, anyhow::Error> {
+    let admin_user_id = SecretStore::get(secrets, "GITHUB_USER_ID")
         .context("Missing expected ENV_VAR: GITHUB_USER_ID")?;
 
-    if !user_id.eq(&admin_user_id) {
+    Ok((github_id.to_string() == admin_user_id).then_some(Admin { github_id }))
+}
+
+/// Only lets the configured admin through and makes them available to
+/// handlers as `Admin`.
+pub async fn auth_middleware(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, ServiceError> {
+    let github_id = authenticate(&state, req.headers())
+        .await?
+        .parse::<i64>()
+        .context("Invalid GitHub user id")?;
+
+    let Some(admin) = as_admin(&state.secrets, github_id)? else {
         return Err(ServiceError::from(anyhow::Error::msg(
             "Unauthorized user action",
         )));
-    }
+    };
+
+    req.extensions_mut().insert(admin);
+    let response = next.run(req).await;
+
+    Ok(response)
+}
 
-    let req = Request::from_parts(parts, body);
+/// Lets any GitHub user through and makes their `User` available to handlers.
+pub async fn user_middleware(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, ServiceError> {
+    let github_id = authenticate(&state, req.headers())
+        .await?
+        .parse::<i64>()
+        .context("Invalid GitHub user id")?;
+    let user = UserDataSource::new(state.pool.clone())
+        .get_or_create_user(github_id)
+        .await?;
+
+    req.extensions_mut().insert(user);
     let response = next.run(req).await;
 
     Ok(response)
 }
+
+/// Like `user_middleware` for requests that send a token, anonymous ones pass as they are.
+pub async fn optional_user_middleware(
+    state: State<AppState>,
+    req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, ServiceError> {
+    if req.headers().contains_key("Authorization") {
+        return Ok(user_middleware(state, req, next).await?.into_response());
+    }
+
+    Ok(next.run(req).await)
+}
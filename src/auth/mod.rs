@@ -1,3 +1,5 @@
 mod authorization;
+mod tokens;
 
 pub use authorization::*;
+pub use tokens::*;
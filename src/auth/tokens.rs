@@ -0,0 +1,100 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use shuttle_runtime::SecretStore;
+
+struct VerifiedToken {
+    github_id: String,
+    valid_until: DateTime<Utc>,
+}
+
+/// Remembers which GitHub user a token belongs to for a short while, so
+/// signed-in reads don't each make a round-trip to GitHub. A token is never
+/// remembered past its own expiry.
+#[derive(Clone)]
+pub struct TokenCache {
+    ttl: TimeDelta,
+    tokens: Arc<Mutex<HashMap<String, VerifiedToken>>>,
+}
+
+impl TokenCache {
+    pub fn new(secrets: &SecretStore) -> Result<Self, anyhow::Error> {
+        let ttl_secs = SecretStore::get(secrets, "AUTH_CACHE_TTL_SECS")
+            .unwrap_or_else(|| "60".to_string())
+            .parse::<i64>()
+            .context("AUTH_CACHE_TTL_SECS is not a valid integer")?;
+
+        Ok(Self::with_ttl(TimeDelta::seconds(ttl_secs)))
+    }
+
+    fn with_ttl(ttl: TimeDelta) -> Self {
+        Self {
+            ttl,
+            tokens: Arc::default(),
+        }
+    }
+
+    pub fn get(&self, access_token: &str) -> Option<String> {
+        let mut tokens = self.tokens.lock().unwrap();
+        match tokens.get(access_token) {
+            Some(token) if token.valid_until > Utc::now() => Some(token.github_id.clone()),
+            Some(_) => {
+                tokens.remove(access_token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, access_token: &str, github_id: &str, expires_at: DateTime<Utc>) {
+        let now = Utc::now();
+        let valid_until = expires_at.min(now + self.ttl);
+        if valid_until <= now {
+            return;
+        }
+
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|_, token| token.valid_until > now);
+        tokens.insert(
+            access_token.to_string(),
+            VerifiedToken {
+                github_id: github_id.to_string(),
+                valid_until,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remembers_token() {
+        let cache = TokenCache::with_ttl(TimeDelta::seconds(60));
+        cache.insert("token", "42", Utc::now() + TimeDelta::hours(1));
+
+        assert_eq!(cache.get("token").as_deref(), Some("42"));
+        assert_eq!(cache.get("other"), None);
+    }
+
+    #[test]
+    fn test_never_outlives_token_expiry() {
+        let cache = TokenCache::with_ttl(TimeDelta::seconds(60));
+        cache.insert("expired", "42", Utc::now() - TimeDelta::seconds(1));
+
+        assert_eq!(cache.get("expired"), None);
+    }
+
+    #[test]
+    fn test_disabled_with_zero_ttl() {
+        let cache = TokenCache::with_ttl(TimeDelta::zero());
+        cache.insert("token", "42", Utc::now() + TimeDelta::hours(1));
+
+        assert_eq!(cache.get("token"), None);
+    }
+}
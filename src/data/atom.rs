@@ -2,6 +2,8 @@ use chrono::{DateTime, Utc};
 use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::{from_value, Value};
 
+use super::xml::optional_text;
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct AtomLink {
     #[serde(rename = "@href")]
@@ -26,6 +28,10 @@ pub struct AtomEntry {
     pub published: Option<DateTime<Utc>>,
     #[serde(deserialize_with = "title")]
     pub title: String,
+    #[serde(deserialize_with = "optional_text", default)]
+    pub summary: Option<String>,
+    #[serde(deserialize_with = "optional_text", default)]
+    pub content: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -85,19 +91,21 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    // Atom Date: 2024-07-23T07:28:00+00:00
-    if let Ok(dt) = DateTime::parse_from_rfc3339(&s) {
-        return Ok(Some(dt.with_timezone(&Utc)));
-    }
 
-    Err(de::Error::custom(&format!(
-        "Failed to parse Atom date: {}",
-        &s
-    )))
+    parse_atom_date(&s)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("Failed to parse Atom date: {}", &s)))
+}
+
+pub fn parse_atom_date(s: &str) -> Option<DateTime<Utc>> {
+    // Atom Date: 2024-07-23T07:28:00+00:00
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
 }
 
 pub fn atom_to_json(value: Value) -> Result<AtomFeed, anyhow::Error> {
-    from_value(value).map_err(|e| anyhow::Error::from(e))
+    from_value(value).map_err(anyhow::Error::from)
 }
 
 #[cfg(test)]
@@ -0,0 +1,166 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{types::Json, FromRow, PgConnection, PgPool};
+
+use super::redact_url;
+
+#[derive(Debug)]
+pub struct AuditInput {
+    pub github_id: i64,
+    pub action: String,
+    pub target_id: Option<i32>,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Deserialize, Serialize, Debug, FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub github_id: i64,
+    pub action: String,
+    pub target_id: Option<i32>,
+    pub before: Option<Json<Value>>,
+    pub after: Option<Json<Value>>,
+    pub created_date: DateTime<Utc>,
+}
+
+pub struct AuditDataSource {
+    pool: PgPool,
+}
+
+impl AuditDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Stores the change with only the fields that differ between `before`
+    /// and `after`, or the whole value when one side is missing. Feed URLs
+    /// are stored without anything that may hold a secret. Written on the
+    /// change's own transaction, so a change is never kept unaudited.
+    pub async fn record(conn: &mut PgConnection, input: AuditInput) -> Result<(), anyhow::Error> {
+        let (mut before, mut after) = diff(input.before, input.after);
+        redact_urls(&mut before);
+        redact_urls(&mut after);
+
+        sqlx::query(
+            "INSERT INTO audit_log (github_id, action, target_id, before, after)
+            VALUES ($1, $2, $3, $4, $5);",
+        )
+        .bind(input.github_id)
+        .bind(&input.action)
+        .bind(input.target_id)
+        .bind((!before.is_null()).then_some(Json(before)))
+        .bind((!after.is_null()).then_some(Json(after)))
+        .execute(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to record audit entry: {}", input.action))?;
+
+        Ok(())
+    }
+
+    pub async fn get_audit_log(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEntry>, anyhow::Error> {
+        let res = sqlx::query_as::<_, AuditEntry>(
+            "SELECT id, github_id, action, target_id, before, after, created_date
+            FROM audit_log
+            ORDER BY id DESC
+            LIMIT $1
+            OFFSET $2;",
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get audit log from db")?;
+
+        Ok(res)
+    }
+}
+
+/// Drops the fields two objects have in common. Anything else is kept whole.
+fn diff(before: Value, after: Value) -> (Value, Value) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(mut after)) => {
+            let mut changed = Map::new();
+            for (key, value) in before {
+                if after.get(&key) == Some(&value) {
+                    after.remove(&key);
+                } else {
+                    changed.insert(key, value);
+                }
+            }
+            (Value::Object(changed), Value::Object(after))
+        }
+        (before, after) => (before, after),
+    }
+}
+
+/// Redacts every `url` field, however deeply nested.
+fn redact_urls(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                match value {
+                    Value::String(url) if key == "url" => *url = redact_url(url),
+                    value => redact_urls(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_urls),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_keeps_changed_fields() {
+        let (before, after) = diff(
+            json!({"id": 1, "name": "Blog", "category": "Code"}),
+            json!({"id": 1, "name": "Blog", "category": "News", "tags": ["News"]}),
+        );
+
+        assert_eq!(before, json!({"category": "Code"}));
+        assert_eq!(after, json!({"category": "News", "tags": ["News"]}));
+    }
+
+    #[test]
+    fn test_diff_keeps_whole_value_without_other_side() {
+        let feed = json!({"id": 1, "name": "Blog"});
+        let (before, after) = diff(Value::Null, feed.clone());
+
+        assert_eq!(before, Value::Null);
+        assert_eq!(after, feed);
+    }
+
+    #[test]
+    fn test_redact_urls() {
+        let mut feeds = json!([
+            {"name": "Blog", "url": "https://example.com/rss?token=abc"},
+            {"name": "Other", "url": "https://other.example.com/atom.xml"}
+        ]);
+        redact_urls(&mut feeds);
+
+        assert_eq!(
+            feeds,
+            json!([
+                {"name": "Blog", "url": "https://example.com/rss"},
+                {"name": "Other", "url": "https://other.example.com/atom.xml"}
+            ])
+        );
+    }
+}
@@ -3,21 +3,42 @@ use std::fmt::{self, Display, Formatter};
 use anyhow::Context;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{types::Json, Connection, FromRow, PgConnection, PgPool, Postgres, Transaction};
 
 #[derive(Clone, Deserialize, Serialize, Debug, FromRow)]
 pub struct CachedEntry {
     pub title: String,
     pub url: String,
     pub created_date: DateTime<Utc>,
+    /// Only set when /feeds is read by a signed-in user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub read: Option<bool>,
+    /// Only stored for search, /feeds leaves it out
+    #[serde(default, skip_serializing)]
+    #[sqlx(default)]
+    pub content: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, FromRow)]
 struct DBCachedFeed {
-    id: i32,
+    raw_feed_id: i32,
     name: String,
     category: String,
-    created_date: DateTime<Utc>, // Used to determine if cache is expired
+    #[sqlx(flatten)]
+    freshness: Freshness,
+    tags: Vec<String>,
+    unread_count: i64,
+    entries: Json<Vec<CachedEntry>>,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize, Debug, FromRow)]
+pub struct Freshness {
+    pub stale: bool,
+    pub failing: bool,
+    pub last_refreshed_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub last_attempt_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
@@ -25,17 +46,53 @@ pub struct CachedFeed {
     pub name: String,
     pub category: String,
     pub entries: Vec<CachedEntry>,
+    #[serde(flatten)]
+    pub freshness: Freshness,
+    /// Tags of the raw feed, filled in by /feeds
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Unread entries inside the requested window, for signed-in users
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unread_count: Option<i64>,
 }
 
-#[derive(Deserialize, Serialize, Debug, FromRow)]
-struct DBCachedFeedName {
-    name: String,
+/// First key of the two-key advisory locks guarding feed refreshes.
+const REFRESH_LOCK_CLASS: i32 = 1;
+
+/// A transaction-scoped advisory lock on one feed's refresh. Everything the
+/// refresh writes goes through `conn` and is committed by `release`; dropping
+/// the lock rolls it all back.
+pub struct RefreshLock {
+    tx: Transaction<'static, Postgres>,
+}
+
+impl RefreshLock {
+    pub fn conn(&mut self) -> &mut PgConnection {
+        &mut self.tx
+    }
+
+    pub async fn release(self) -> Result<(), anyhow::Error> {
+        self.tx
+            .commit()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to commit transaction")
+    }
+}
+
+/// Keeps feeds tagged with any, or with `match_all` every one, of `tags`.
+pub struct TagFilter {
+    pub tags: Vec<String>,
+    pub match_all: bool,
 }
 
 pub struct CacheDataSource {
     pool: PgPool,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Deserialize, Debug, Clone, Copy)]
 pub enum Duration {
     DAY,
@@ -55,81 +112,210 @@ impl Display for Duration {
     }
 }
 
+impl Duration {
+    pub fn days(&self) -> i32 {
+        match self {
+            Duration::DAY => 1,
+            Duration::WEEK => 7,
+            Duration::MONTH => 30,
+            Duration::YEAR => 365,
+        }
+    }
+}
+
 impl CacheDataSource {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
 
-    pub async fn get_cached_feed(
+    /// Returns every cached feed with its newest entries inside the window,
+    /// paired with the id of the raw feed it belongs to, in a single query.
+    /// Deleted, inactive and paused feeds are left out.
+    /// With a `reader`, only their subscriptions are returned, filed under their
+    /// own categories, and entries carry their read state. Readers without any
+    /// subscriptions get every feed.
+    pub async fn get_cached_feeds(
         &self,
-        feed_name: &str,
         duration: Duration,
         max_entries: usize,
-    ) -> Result<Option<CachedFeed>, anyhow::Error> {
-        let cached_feed = sqlx::query_as::<_, DBCachedFeed>(
+        cache_duration: i32,
+        reader: Option<i32>,
+        unread_only: bool,
+        tag_filter: Option<&TagFilter>,
+    ) -> Result<Vec<(i32, CachedFeed)>, anyhow::Error> {
+        let mut filter_tags = tag_filter.map(|filter| filter.tags.clone());
+        if let Some(tags) = filter_tags.as_mut() {
+            tags.sort_unstable();
+            tags.dedup();
+        }
+
+        let cached_feeds = sqlx::query_as::<_, DBCachedFeed>(
             r#"SELECT
-                cached.id,
+                raw.id AS raw_feed_id,
                 cached.name,
-                c.name as category,
-                cached.created_date
-            FROM cached_feeds cached
+                COALESCE(sub.category, c.name) as category,
+                cached.last_refreshed_at IS NULL
+                    OR cached.last_refreshed_at < NOW() - make_interval(mins => $3) AS stale,
+                cached.last_error IS NOT NULL AS failing,
+                cached.last_refreshed_at,
+                cached.last_attempt_at,
+                ARRAY(
+                    SELECT t.name FROM raw_feed_tags ft
+                    JOIN tags t ON t.id = ft.tag_id
+                    WHERE ft.feed_id = raw.id
+                    ORDER BY t.name
+                ) AS tags,
+                unread.count AS unread_count,
+                COALESCE(
+                    json_agg(
+                        json_build_object(
+                            'title', entry.title,
+                            'url', entry.url,
+                            'created_date', entry.created_date,
+                            'read', entry.read
+                        )
+                        ORDER BY entry.created_date DESC
+                    ) FILTER (WHERE entry.url IS NOT NULL),
+                    '[]'
+                ) AS entries
+            FROM raw_feeds raw
+            JOIN cached_feeds cached ON cached.name = raw.name
             JOIN categories c ON cached.category_id = c.id
-            WHERE cached.name = $1;"#,
+            LEFT JOIN subscriptions sub ON sub.feed_id = raw.id AND sub.user_id = $4
+            LEFT JOIN LATERAL (
+                SELECT max(m.read_until) AS read_until
+                FROM read_markers m
+                WHERE m.user_id = $4
+                AND (m.feed_id = raw.id
+                    OR m.category = COALESCE(sub.category, c.name)
+                    OR num_nonnulls(m.feed_id, m.category) = 0)
+            ) marker ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT ce.title, ce.url, ce.created_date, state.read
+                FROM cached_entries ce,
+                LATERAL (
+                    SELECT COALESCE(ce.created_date <= marker.read_until, FALSE)
+                        OR EXISTS (
+                            SELECT 1 FROM entry_reads r
+                            WHERE r.user_id = $4 AND r.entry_id = ce.id
+                        ) AS read
+                ) state
+                WHERE ce.feed_id = cached.id
+                AND ce.created_date >= CURRENT_DATE - make_interval(days => $1)
+                AND NOT ($5 AND state.read)
+                ORDER BY ce.created_date DESC
+                LIMIT $2
+            ) entry ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT count(*) AS count
+                FROM cached_entries ce
+                WHERE $4::int IS NOT NULL
+                AND ce.feed_id = cached.id
+                AND ce.created_date >= CURRENT_DATE - make_interval(days => $1)
+                AND NOT COALESCE(ce.created_date <= marker.read_until, FALSE)
+                AND NOT EXISTS (
+                    SELECT 1 FROM entry_reads r
+                    WHERE r.user_id = $4 AND r.entry_id = ce.id
+                )
+            ) unread ON TRUE
+            WHERE feed_enabled(raw)
+            AND ($4::int IS NULL OR sub.id IS NOT NULL OR NOT EXISTS (
+                SELECT 1 FROM subscriptions own
+                JOIN raw_feeds f ON f.id = own.feed_id
+                WHERE own.user_id = $4 AND f.deleted_at IS NULL
+            ))
+            AND ($6::varchar[] IS NULL OR (
+                SELECT count(*)
+                FROM raw_feed_tags ft
+                JOIN tags t ON t.id = ft.tag_id
+                WHERE ft.feed_id = raw.id AND t.name = ANY($6)
+            ) >= CASE WHEN $7 THEN cardinality($6) ELSE 1 END)
+            GROUP BY raw.id, cached.id, c.name, sub.category, unread.count
+            ORDER BY raw.id;"#,
         )
-        .bind(feed_name)
-        .fetch_optional(&self.pool)
+        .bind(duration.days())
+        .bind(i32::try_from(max_entries).unwrap_or(i32::MAX))
+        .bind(cache_duration)
+        .bind(reader)
+        .bind(unread_only)
+        .bind(filter_tags)
+        .bind(tag_filter.is_some_and(|filter| filter.match_all))
+        .fetch_all(&self.pool)
         .await
         .inspect_err(|e| {
             eprintln!("Database error: {:?}", e);
         })
-        .context(format!("Failed to get cached feed: {}", feed_name))?;
-
-        if let Some(feed) = cached_feed {
-            let cached_entries = sqlx::query_as::<_, CachedEntry>(
-                r#"SELECT
-                    title,
-                    url,
-                    created_date
-                FROM cached_entries
-                WHERE feed_id = $1
-                AND CASE
-                    WHEN $2 = 'DAY' then created_date >= CURRENT_DATE - INTERVAL '1 days'
-                    WHEN $2 = 'WEEK' then created_date >= CURRENT_DATE - INTERVAL '7 days'
-                    WHEN $2 = 'MONTH' then created_date >= CURRENT_DATE - INTERVAL '30 days'
-                    WHEN $2 = 'YEAR' then created_date >= CURRENT_DATE - INTERVAL '365 days'
-                    ELSE TRUE
-                END
-                ORDER BY created_date DESC
-                LIMIT $3;"#,
-            )
-            .bind(feed.id)
-            .bind(duration.to_string())
-            .bind(max_entries as i32)
-            .fetch_all(&self.pool)
+        .context("Failed to get cached feeds")?;
+
+        Ok(cached_feeds
+            .into_iter()
+            .map(|feed| {
+                let mut entries = feed.entries.0;
+                if reader.is_none() {
+                    entries.iter_mut().for_each(|entry| entry.read = None);
+                }
+                (
+                    feed.raw_feed_id,
+                    CachedFeed {
+                        name: feed.name,
+                        category: feed.category,
+                        entries,
+                        freshness: feed.freshness,
+                        tags: feed.tags,
+                        unread_count: reader.map(|_| feed.unread_count),
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Takes the refresh lock for a feed. If another process holds it, waits
+    /// for that refresh to finish and returns `None` instead of a lock.
+    pub async fn lock_refresh(&self, feed_id: i32) -> Result<Option<RefreshLock>, anyhow::Error> {
+        let mut tx = self
+            .pool
+            .begin()
             .await
             .inspect_err(|e| {
                 eprintln!("Database error: {:?}", e);
             })
-            .context(format!(
-                "Failed to get cached entries for feed: {}",
-                feed.name
-            ))?;
+            .context("Failed to start transaction")?;
 
-            return Ok(Some(CachedFeed {
-                name: feed.name,
-                category: feed.category,
-                entries: cached_entries,
-            }));
+        let acquired: bool = sqlx::query_scalar("SELECT pg_try_advisory_xact_lock($1, $2);")
+            .bind(REFRESH_LOCK_CLASS)
+            .bind(feed_id)
+            .fetch_one(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!("Failed to lock refresh of feed: {}", feed_id))?;
+
+        if acquired {
+            return Ok(Some(RefreshLock { tx }));
         }
 
-        return Ok(None);
+        sqlx::query("SELECT pg_advisory_xact_lock($1, $2);")
+            .bind(REFRESH_LOCK_CLASS)
+            .bind(feed_id)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!("Failed to wait for refresh of feed: {}", feed_id))?;
+
+        Ok(None)
     }
 
-    pub async fn cache_feed(&self, input: CachedFeed) -> Result<(), anyhow::Error> {
+    /// Caches a fetched feed on the connection holding its refresh lock.
+    pub async fn cache_feed(
+        conn: &mut PgConnection,
+        input: CachedFeed,
+    ) -> Result<u64, anyhow::Error> {
         println!("Caching feed: {}", input.name);
 
-        let mut tx = self
-            .pool
+        let mut tx = conn
             .begin()
             .await
             .inspect_err(|e| {
@@ -147,8 +333,13 @@ impl CacheDataSource {
             .context("Failed to fetch existing category ID")?;
 
         let cached_feed_id: i32 = sqlx::query_scalar(
-            "INSERT INTO cached_feeds (name, category_id)
-            VALUES ($1, $2)
+            "INSERT INTO cached_feeds (name, category_id, last_refreshed_at, last_attempt_at)
+            VALUES ($1, $2, NOW(), NOW())
+            ON CONFLICT (name) DO UPDATE
+            SET category_id = EXCLUDED.category_id,
+                last_refreshed_at = NOW(),
+                last_attempt_at = NOW(),
+                last_error = NULL
             RETURNING id",
         )
         .bind(&input.name)
@@ -158,16 +349,36 @@ impl CacheDataSource {
         .inspect_err(|e| eprintln!("Database error: {:?}", e))
         .context(format!("Error while caching feed: {}", input.name))?;
 
+        let mut inserted = 0;
         for entry in input.entries {
-            sqlx::query(
-                "INSERT INTO cached_entries (feed_id, title, url, created_date)
-                VALUES ($1, $2, $3, $4)",
+            // xmax is only zero for freshly inserted rows, not for updated ones
+            let is_new: Option<bool> = sqlx::query_scalar(
+                "INSERT INTO cached_entries (feed_id, title, url, created_date, content)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (url) DO UPDATE
+                SET feed_id = EXCLUDED.feed_id,
+                    title = EXCLUDED.title,
+                    created_date = EXCLUDED.created_date,
+                    content = EXCLUDED.content
+                WHERE (
+                    cached_entries.feed_id,
+                    cached_entries.title,
+                    cached_entries.created_date,
+                    cached_entries.content
+                ) IS DISTINCT FROM (
+                    EXCLUDED.feed_id,
+                    EXCLUDED.title,
+                    EXCLUDED.created_date,
+                    EXCLUDED.content
+                )
+                RETURNING xmax = 0",
             )
             .bind(cached_feed_id)
             .bind(&entry.title)
             .bind(&entry.url)
-            .bind(&entry.created_date)
-            .execute(&mut *tx)
+            .bind(entry.created_date)
+            .bind(&entry.content)
+            .fetch_optional(&mut *tx)
             .await
             .inspect_err(|e| {
                 eprintln!("Database error: {:?}", e);
@@ -176,6 +387,9 @@ impl CacheDataSource {
                 "Failed to cache entry '{}' for feed: {}",
                 &entry.title, &input.name
             ))?;
+            if is_new == Some(true) {
+                inserted += 1;
+            }
         }
 
         tx.commit()
@@ -185,13 +399,46 @@ impl CacheDataSource {
             })
             .context("Failed to commit transaction")?;
 
+        Ok(inserted)
+    }
+
+    /// Records a failed refresh without touching the last good entries.
+    pub async fn record_refresh_failure(
+        conn: &mut PgConnection,
+        feed_name: &str,
+        error: &str,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            "UPDATE cached_feeds
+            SET last_attempt_at = NOW(), last_error = $2
+            WHERE name = $1;",
+        )
+        .bind(feed_name)
+        .bind(error)
+        .execute(&mut *conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!(
+            "Failed to record refresh failure for feed: {}",
+            feed_name
+        ))?;
+
         Ok(())
     }
 
-    pub async fn cache_clear(&self, cache_duration: i32) -> Result<(), anyhow::Error> {
-        let stale_cache = sqlx::query_as::<_, DBCachedFeedName>(
-            "SELECT * FROM cached_feeds
-            WHERE created_date < NOW() - INTERVAL '$1 minutes';",
+    pub async fn get_stale_feed_names(
+        &self,
+        cache_duration: i32,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let res = sqlx::query_scalar::<_, String>(
+            "SELECT raw.name
+            FROM raw_feeds raw
+            LEFT JOIN cached_feeds cached ON cached.name = raw.name
+            WHERE feed_enabled(raw)
+            AND (cached.last_refreshed_at IS NULL
+                OR cached.last_refreshed_at < NOW() - make_interval(mins => $1));",
         )
         .bind(cache_duration)
         .fetch_all(&self.pool)
@@ -199,25 +446,324 @@ impl CacheDataSource {
         .inspect_err(|e| {
             eprintln!("Database error: {:?}", e);
         })
-        .context("Failed to fetch stale cached feeds")?;
+        .context("Failed to get stale feeds")?;
+
+        Ok(res)
+    }
 
-        let stale_names: Vec<String> = stale_cache.iter().map(|c| c.name.clone()).collect();
-        if !stale_names.is_empty() {
-            println!("Clearing stale cache items: [{}]", stale_names.join(", "));
+    /// Entries are kept across refreshes, so feeds that were purged or
+    /// renamed have to be dropped explicitly. Starred entries stay, along
+    /// with the cached feed they belong to.
+    pub async fn cache_clear(&self) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            "DELETE FROM cached_entries e
+            USING cached_feeds cached
+            WHERE cached.id = e.feed_id
+            AND cached.name NOT IN (SELECT name FROM raw_feeds)
+            AND NOT EXISTS (SELECT 1 FROM starred_entries s WHERE s.entry_id = e.id);",
+        )
+        .execute(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to clear orphaned cached entries")?;
 
-            sqlx::query_as::<_, DBCachedFeed>(
-                "DELETE FROM cached_feeds
-                WHERE created_date < NOW() - INTERVAL '$1 minutes';",
+        let orphaned_names: Vec<String> = sqlx::query_scalar(
+            "DELETE FROM cached_feeds cached
+            WHERE name NOT IN (SELECT name FROM raw_feeds)
+            AND NOT EXISTS (SELECT 1 FROM cached_entries e WHERE e.feed_id = cached.id)
+            RETURNING name;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to clear orphaned cached feeds")?;
+
+        if !orphaned_names.is_empty() {
+            println!(
+                "Clearing orphaned cache items: [{}]",
+                orphaned_names.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use sqlx::Executor;
+
+    use crate::{
+        data::{ReadMarkerInput, ReadStateDataSource, ScratchDatabase},
+        error::ClientError,
+    };
+
+    use super::*;
+
+    const SEEDED_FEEDS: i32 = 300;
+    const ENTRIES_PER_FEED: i32 = 20;
+
+    /// Builds an isolated schema on the database at `DATABASE_URL` and seeds it.
+    async fn seeded_database(schema: &str) -> ScratchDatabase {
+        let database = ScratchDatabase::new(schema).await;
+        let pool = &database.pool;
+
+        sqlx::query("INSERT INTO categories (name) VALUES ('bench')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO raw_feeds (name, url, category_id)
+            SELECT 'feed-' || i, 'https://example.com/' || i, c.id
+            FROM generate_series(1, $1) i, categories c",
+        )
+        .bind(SEEDED_FEEDS)
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO cached_feeds (name, category_id)
+            SELECT name, category_id FROM raw_feeds",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO cached_entries (feed_id, title, url, created_date)
+            SELECT f.id, 'entry ' || e, 'https://example.com/' || f.id || '/' || e,
+                NOW() - make_interval(hours => e * 6)
+            FROM cached_feeds f, generate_series(1, $1) e",
+        )
+        .bind(ENTRIES_PER_FEED)
+        .execute(pool)
+        .await
+        .unwrap();
+        pool.execute("ANALYZE").await.unwrap();
+
+        database
+    }
+
+    /// The per-feed lookups `/feeds` used to make before `get_cached_feeds`.
+    async fn get_cached_feeds_one_by_one(pool: &PgPool, duration: Duration) -> Vec<CachedFeed> {
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM raw_feeds ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+
+        let mut feeds = Vec::new();
+        for name in names {
+            let (id, category): (i32, String) = sqlx::query_as(
+                "SELECT cached.id, c.name
+                FROM cached_feeds cached
+                JOIN categories c ON cached.category_id = c.id
+                WHERE cached.name = $1;",
             )
-            .bind(cache_duration)
-            .fetch_all(&self.pool)
+            .bind(&name)
+            .fetch_one(pool)
             .await
-            .inspect_err(|e| {
-                eprintln!("Database error: {:?}", e);
-            })
-            .context("Failed to clear stale cached feeds")?;
+            .unwrap();
+            let entries = sqlx::query_as::<_, CachedEntry>(
+                "SELECT title, url, created_date
+                FROM cached_entries
+                WHERE feed_id = $1
+                AND CASE
+                    WHEN $2 = 'DAY' then created_date >= CURRENT_DATE - INTERVAL '1 days'
+                    WHEN $2 = 'WEEK' then created_date >= CURRENT_DATE - INTERVAL '7 days'
+                    WHEN $2 = 'MONTH' then created_date >= CURRENT_DATE - INTERVAL '30 days'
+                    WHEN $2 = 'YEAR' then created_date >= CURRENT_DATE - INTERVAL '365 days'
+                    ELSE TRUE
+                END
+                ORDER BY created_date DESC
+                LIMIT $3;",
+            )
+            .bind(id)
+            .bind(duration.to_string())
+            .bind(5)
+            .fetch_all(pool)
+            .await
+            .unwrap();
+            feeds.push(CachedFeed {
+                name,
+                category,
+                entries,
+                freshness: Freshness::default(),
+                tags: Vec::new(),
+                unread_count: None,
+            });
         }
+        feeds
+    }
 
-        Ok(())
+    // Timings are printed for comparison only, they are too noisy to assert on:
+    // cargo test --release -- --ignored --nocapture test_get_cached_feeds_matches_one_by_one
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_get_cached_feeds_matches_one_by_one() {
+        let schema = "get_cached_feeds_matches_one_by_one";
+        let database = seeded_database(schema).await;
+        let pool = database.pool.clone();
+        let datasource = CacheDataSource::new(pool.clone());
+        let runs = 20;
+
+        let timer = Instant::now();
+        let mut expected = Vec::new();
+        for _ in 0..runs {
+            expected = get_cached_feeds_one_by_one(&pool, Duration::WEEK).await;
+        }
+        let one_by_one = timer.elapsed() / runs;
+
+        let timer = Instant::now();
+        let mut actual = Vec::new();
+        for _ in 0..runs {
+            actual = datasource
+                .get_cached_feeds(Duration::WEEK, 5, 60, None, false, None)
+                .await
+                .unwrap();
+        }
+        let single_query = timer.elapsed() / runs;
+
+        println!(
+            "[{}] feeds: one by one {:?}, single query {:?}",
+            SEEDED_FEEDS, one_by_one, single_query
+        );
+
+        assert_eq!(actual.len(), expected.len());
+        for ((_, actual), expected) in actual.iter().zip(&expected) {
+            assert_eq!(actual.name, expected.name);
+            let urls = |feed: &CachedFeed| -> Vec<String> {
+                feed.entries.iter().map(|entry| entry.url.clone()).collect()
+            };
+            assert_eq!(urls(actual), urls(expected));
+        }
+
+        database.drop().await;
+    }
+
+    /// Two feeds, `news` and `tech`, with one entry each, and two readers:
+    /// `anonymous` has no subscriptions, `subscriber` follows `tech` under `mine`.
+    async fn reader_database(schema: &str) -> (ScratchDatabase, i32, i32) {
+        let database = ScratchDatabase::new(schema).await;
+        let pool = &database.pool;
+        sqlx::query(
+            "WITH c AS (
+                INSERT INTO categories (name) VALUES ('news'), ('tech') RETURNING id, name
+            ),
+            raw AS (
+                INSERT INTO raw_feeds (name, url, category_id)
+                SELECT c.name, 'https://example.com/' || c.name, c.id FROM c
+                RETURNING name, category_id
+            ),
+            cached AS (
+                INSERT INTO cached_feeds (name, category_id)
+                SELECT name, category_id FROM raw
+                RETURNING id, name
+            )
+            INSERT INTO cached_entries (feed_id, title, url, created_date)
+            SELECT id, 'entry', 'https://example.com/' || name || '/entry', NOW() - INTERVAL '1 hour'
+            FROM cached;",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        let users: Vec<i32> =
+            sqlx::query_scalar("INSERT INTO users (github_id) VALUES (1), (2) RETURNING id")
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        sqlx::query(
+            "INSERT INTO subscriptions (user_id, feed_id, category)
+            SELECT $1, id, 'mine' FROM raw_feeds WHERE name = 'tech';",
+        )
+        .bind(users[1])
+        .execute(pool)
+        .await
+        .unwrap();
+
+        (database, users[0], users[1])
+    }
+
+    fn category_marker(category: &str) -> ReadMarkerInput {
+        ReadMarkerInput {
+            feed_id: None,
+            category: Some(category.to_string()),
+            until: None,
+        }
+    }
+
+    async fn unread(datasource: &CacheDataSource, reader: i32) -> Vec<(String, String, i64)> {
+        datasource
+            .get_cached_feeds(Duration::WEEK, 5, 60, Some(reader), false, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, feed)| (feed.name, feed.category, feed.unread_count.unwrap()))
+            .collect()
+    }
+
+    fn feed(name: &str, category: &str, unread: i64) -> (String, String, i64) {
+        (name.to_string(), category.to_string(), unread)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_subscriber_sees_only_subscriptions() {
+        let (database, anonymous, subscriber) =
+            reader_database("test_subscriber_sees_only_subscriptions").await;
+        let datasource = CacheDataSource::new(database.pool.clone());
+
+        assert_eq!(
+            unread(&datasource, anonymous).await,
+            vec![feed("news", "news", 1), feed("tech", "tech", 1)]
+        );
+        assert_eq!(
+            unread(&datasource, subscriber).await,
+            vec![feed("tech", "mine", 1)]
+        );
+
+        database.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_category_markers() {
+        let (database, anonymous, subscriber) = reader_database("test_category_markers").await;
+        let datasource = CacheDataSource::new(database.pool.clone());
+        let read_state = ReadStateDataSource::new(database.pool.clone());
+
+        // Without subscriptions, the feeds' own categories are used
+        read_state
+            .mark_read_until(anonymous, category_marker("news"))
+            .await
+            .unwrap();
+        assert_eq!(
+            unread(&datasource, anonymous).await,
+            vec![feed("news", "news", 0), feed("tech", "tech", 1)]
+        );
+
+        // Subscribers only have their own categories
+        let err = read_state
+            .mark_read_until(subscriber, category_marker("tech"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::NotFound(_))
+        ));
+        read_state
+            .mark_read_until(subscriber, category_marker("mine"))
+            .await
+            .unwrap();
+        assert_eq!(
+            unread(&datasource, subscriber).await,
+            vec![feed("tech", "mine", 0)]
+        );
+
+        database.drop().await;
     }
 }
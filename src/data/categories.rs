@@ -0,0 +1,543 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, FromRow, PgConnection, PgPool};
+
+use crate::error::{is_unique_violation, ClientError};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CategoryInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+}
+
+/// The full list of category ids in display order. Categories left out are
+/// placed after the listed ones.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CategoryOrderInput {
+    pub ids: Vec<i32>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CategoryMergeInput {
+    pub into: i32,
+}
+
+#[derive(Deserialize, Serialize, Debug, FromRow)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub position: i32,
+}
+
+#[derive(Default, Deserialize, Serialize, Debug)]
+pub struct MergeReport {
+    pub feeds_moved: u64,
+}
+
+pub struct CategoryDataSource {
+    pool: PgPool,
+}
+
+impl CategoryDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_categories(&self) -> Result<Vec<Category>, anyhow::Error> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to acquire connection")?;
+
+        Self::fetch_categories(&mut conn).await
+    }
+
+    /// Like `get_categories`, inside a change's transaction.
+    pub async fn fetch_categories(conn: &mut PgConnection) -> Result<Vec<Category>, anyhow::Error> {
+        let res = sqlx::query_as::<_, Category>(
+            "SELECT id, name, description, color, icon, position
+            FROM categories
+            ORDER BY position ASC, name ASC;",
+        )
+        .fetch_all(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get categories from db")?;
+
+        Ok(res)
+    }
+
+    pub async fn get_category(
+        conn: &mut PgConnection,
+        id: i32,
+    ) -> Result<Option<Category>, anyhow::Error> {
+        let res = sqlx::query_as::<_, Category>(
+            "SELECT id, name, description, color, icon, position
+            FROM categories
+            WHERE id = $1;",
+        )
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to get category: {}", id))?;
+
+        Ok(res)
+    }
+
+    /// Creates a managed category at the end of the current order.
+    pub async fn create_category(
+        conn: &mut PgConnection,
+        input: CategoryInput,
+    ) -> Result<Category, anyhow::Error> {
+        let name = category_name(&input.name)?;
+
+        let res = sqlx::query_as::<_, Category>(
+            "INSERT INTO categories (name, description, color, icon, position, managed)
+            SELECT $1, $2, $3, $4, COALESCE(MAX(position), 0) + 1, TRUE FROM categories
+            ON CONFLICT (name) DO NOTHING
+            RETURNING id, name, description, color, icon, position;",
+        )
+        .bind(name)
+        .bind(&input.description)
+        .bind(&input.color)
+        .bind(&input.icon)
+        .fetch_optional(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to create category: {}", name))?;
+
+        res.ok_or_else(|| {
+            ClientError::Conflict(format!("Category already exists: {}", name)).into()
+        })
+    }
+
+    /// Renames a category and replaces its display metadata. Feeds in it are
+    /// retagged with the new name.
+    pub async fn update_category(
+        conn: &mut PgConnection,
+        id: i32,
+        input: CategoryInput,
+    ) -> Result<(), anyhow::Error> {
+        let name = category_name(&input.name)?;
+
+        let mut tx = conn
+            .begin()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to start transaction")?;
+
+        let old_name: String =
+            sqlx::query_scalar("SELECT name FROM categories WHERE id = $1 FOR UPDATE;")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await
+                .inspect_err(|e| {
+                    eprintln!("Database error: {:?}", e);
+                })
+                .context(format!("Failed to get category: {}", id))?
+                .ok_or_else(|| ClientError::NotFound(format!("Unknown category: {}", id)))?;
+
+        sqlx::query(
+            "UPDATE categories
+            SET name = $2, description = $3, color = $4, icon = $5, managed = TRUE
+            WHERE id = $1;",
+        )
+        .bind(id)
+        .bind(name)
+        .bind(&input.description)
+        .bind(&input.color)
+        .bind(&input.icon)
+        .execute(&mut *tx)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to update category: {}", id))
+        .map_err(|e| {
+            if is_unique_violation(&e) {
+                ClientError::Conflict(format!("Category already exists: {}", name)).into()
+            } else {
+                e
+            }
+        })?;
+
+        if old_name != name {
+            retag_category_feeds(&mut tx, id, &old_name, name).await?;
+        }
+
+        tx.commit()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to commit transaction")?;
+
+        Ok(())
+    }
+
+    pub async fn reorder_categories(
+        conn: &mut PgConnection,
+        input: CategoryOrderInput,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            "UPDATE categories
+            SET position = COALESCE(array_position($1::int[], id), cardinality($1::int[]) + 1);",
+        )
+        .bind(&input.ids)
+        .execute(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to reorder categories")?;
+
+        Ok(())
+    }
+
+    /// Moves every feed, cached feed and retention rule of category `id` over
+    /// to `input.into`, then deletes `id`. A retention rule already on the
+    /// target wins over the merged one.
+    pub async fn merge_categories(
+        conn: &mut PgConnection,
+        id: i32,
+        input: CategoryMergeInput,
+    ) -> Result<MergeReport, anyhow::Error> {
+        if id == input.into {
+            return Err(ClientError::BadRequest(
+                "A category cannot be merged into itself".to_string(),
+            )
+            .into());
+        }
+
+        let mut tx = conn
+            .begin()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to start transaction")?;
+
+        let names: Vec<(i32, String)> = sqlx::query_as(
+            "SELECT id, name FROM categories WHERE id = ANY($1) ORDER BY id FOR UPDATE;",
+        )
+        .bind([id, input.into])
+        .fetch_all(&mut *tx)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get categories to merge")?;
+
+        let name_of = |category_id: i32| {
+            names
+                .iter()
+                .find(|(id, _)| *id == category_id)
+                .map(|(_, name)| name.as_str())
+                .ok_or_else(|| ClientError::NotFound(format!("Unknown category: {}", category_id)))
+        };
+        let (source, target) = (name_of(id)?, name_of(input.into)?);
+
+        retag_category_feeds(&mut tx, id, source, target).await?;
+
+        sqlx::query(
+            "DELETE FROM retention_policies
+            WHERE category_id = $1
+                AND EXISTS (SELECT 1 FROM retention_policies WHERE category_id = $2);",
+        )
+        .bind(id)
+        .bind(input.into)
+        .execute(&mut *tx)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to drop merged retention rule")?;
+
+        sqlx::query("UPDATE retention_policies SET category_id = $2 WHERE category_id = $1;")
+            .bind(id)
+            .bind(input.into)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to move retention rule")?;
+
+        let moved = sqlx::query("UPDATE raw_feeds SET category_id = $2 WHERE category_id = $1;")
+            .bind(id)
+            .bind(input.into)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to move feeds")?;
+
+        sqlx::query("UPDATE cached_feeds SET category_id = $2 WHERE category_id = $1;")
+            .bind(id)
+            .bind(input.into)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to move cached feeds")?;
+
+        sqlx::query("DELETE FROM categories WHERE id = $1;")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!("Failed to delete category: {}", id))?;
+
+        tx.commit()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to commit transaction")?;
+
+        println!("Merged category '{}' into '{}'", source, target);
+
+        Ok(MergeReport {
+            feeds_moved: moved.rows_affected(),
+        })
+    }
+}
+
+fn category_name(name: &str) -> Result<&str, anyhow::Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ClientError::BadRequest("Category name cannot be empty".to_string()).into());
+    }
+    Ok(name)
+}
+
+/// Swaps the category tag of every feed in `category_id` from `old` to `new`,
+/// keeping the category tagged as it is everywhere else. The old links go
+/// first, dropping them deletes tags nothing links to anymore.
+async fn retag_category_feeds(
+    conn: &mut PgConnection,
+    category_id: i32,
+    old: &str,
+    new: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query(
+        "DELETE FROM raw_feed_tags rt
+        USING tags t, raw_feeds r
+        WHERE rt.tag_id = t.id AND t.name = $2
+            AND rt.feed_id = r.id AND r.category_id = $1;",
+    )
+    .bind(category_id)
+    .bind(old)
+    .execute(&mut *conn)
+    .await
+    .inspect_err(|e| {
+        eprintln!("Database error: {:?}", e);
+    })
+    .context(format!("Failed to untag feeds from: {}", old))?;
+
+    sqlx::query("INSERT INTO tags (name) VALUES ($1) ON CONFLICT (name) DO NOTHING;")
+        .bind(new)
+        .execute(&mut *conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to create tag: {}", new))?;
+
+    sqlx::query(
+        "INSERT INTO raw_feed_tags (feed_id, tag_id)
+        SELECT r.id, t.id FROM raw_feeds r, tags t
+        WHERE r.category_id = $1 AND t.name = $2
+        ON CONFLICT DO NOTHING;",
+    )
+    .bind(category_id)
+    .bind(new)
+    .execute(&mut *conn)
+    .await
+    .inspect_err(|e| {
+        eprintln!("Database error: {:?}", e);
+    })
+    .context(format!("Failed to tag feeds with: {}", new))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{FeedDataSource, ParseOverrides, RawFeedInput, ScratchDatabase};
+
+    async fn create_feed(pool: &PgPool, name: &str, category: &str, tags: &[&str]) -> i32 {
+        let mut conn = pool.acquire().await.unwrap();
+        FeedDataSource::create_raw_feed(
+            &mut conn,
+            RawFeedInput {
+                name: name.to_string(),
+                url: format!("https://example.com/{}", name),
+                category: category.to_string(),
+                parse_overrides: ParseOverrides::default(),
+                credentials: None,
+                tags: Some(tags.iter().map(|tag| tag.to_string()).collect()),
+            },
+            None,
+        )
+        .await
+        .unwrap()
+        .id
+    }
+
+    async fn tags_of(pool: &PgPool, id: i32) -> Vec<String> {
+        FeedDataSource::new(pool.clone())
+            .get_raw_feed(id)
+            .await
+            .unwrap()
+            .unwrap()
+            .tags
+    }
+
+    async fn all_tags(pool: &PgPool) -> Vec<String> {
+        sqlx::query_scalar("SELECT name FROM tags ORDER BY name")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    fn category_input(name: &str) -> CategoryInput {
+        CategoryInput {
+            name: name.to_string(),
+            description: None,
+            color: None,
+            icon: None,
+        }
+    }
+
+    fn is_client_error(err: &anyhow::Error, expected: fn(&ClientError) -> bool) -> bool {
+        err.chain()
+            .any(|cause| cause.downcast_ref::<ClientError>().is_some_and(expected))
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_rename_retags_feeds() {
+        let database = ScratchDatabase::new("test_rename_retags_feeds").await;
+        let pool = &database.pool;
+        let datasource = CategoryDataSource::new(pool.clone());
+        let mut conn = pool.acquire().await.unwrap();
+        let feed = create_feed(pool, "feed", "news", &["rust"]).await;
+        create_feed(pool, "other", "sports", &["news"]).await;
+        let news = datasource.get_categories().await.unwrap()[0].id;
+
+        CategoryDataSource::update_category(&mut conn, news, category_input("world"))
+            .await
+            .unwrap();
+
+        assert_eq!(tags_of(pool, feed).await, vec!["rust", "world"]);
+        // Still carried as a plain tag by the other feed
+        assert_eq!(
+            all_tags(pool).await,
+            vec!["news", "rust", "sports", "world"]
+        );
+
+        let err = CategoryDataSource::update_category(&mut conn, news, category_input("sports"))
+            .await
+            .unwrap_err();
+        assert!(is_client_error(&err, |e| matches!(
+            e,
+            ClientError::Conflict(_)
+        )));
+        let err = CategoryDataSource::create_category(&mut conn, category_input("world"))
+            .await
+            .unwrap_err();
+        assert!(is_client_error(&err, |e| matches!(
+            e,
+            ClientError::Conflict(_)
+        )));
+
+        drop(conn);
+        database.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_merge_moves_feeds_and_tags() {
+        let database = ScratchDatabase::new("test_merge_moves_feeds_and_tags").await;
+        let pool = &database.pool;
+        let datasource = CategoryDataSource::new(pool.clone());
+        let mut conn = pool.acquire().await.unwrap();
+        let first = create_feed(pool, "first", "news", &["rust"]).await;
+        let second = create_feed(pool, "second", "world", &[]).await;
+        let ids: Vec<i32> = datasource
+            .get_categories()
+            .await
+            .unwrap()
+            .iter()
+            .map(|category| category.id)
+            .collect();
+        let (news, world) = (ids[0], ids[1]);
+
+        let report = CategoryDataSource::merge_categories(
+            &mut conn,
+            news,
+            CategoryMergeInput { into: world },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(report.feeds_moved, 1);
+        assert_eq!(tags_of(pool, first).await, vec!["rust", "world"]);
+        assert_eq!(tags_of(pool, second).await, vec!["world"]);
+        assert_eq!(all_tags(pool).await, vec!["rust", "world"]);
+        assert!(CategoryDataSource::get_category(&mut conn, news)
+            .await
+            .unwrap()
+            .is_none());
+
+        let err = CategoryDataSource::merge_categories(
+            &mut conn,
+            world,
+            CategoryMergeInput { into: world },
+        )
+        .await
+        .unwrap_err();
+        assert!(is_client_error(&err, |e| matches!(
+            e,
+            ClientError::BadRequest(_)
+        )));
+        let err = CategoryDataSource::merge_categories(
+            &mut conn,
+            news,
+            CategoryMergeInput { into: world },
+        )
+        .await
+        .unwrap_err();
+        assert!(is_client_error(&err, |e| matches!(
+            e,
+            ClientError::NotFound(_)
+        )));
+
+        drop(conn);
+        database.drop().await;
+    }
+}
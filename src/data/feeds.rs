@@ -1,17 +1,25 @@
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{types::Json, Connection, FromRow, PgConnection, PgPool};
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct RawFeedInput {
-    name: String,
-    url: String,
-    category: String,
-}
+use super::{FeedCredentials, ParseOverrides};
+use crate::error::ClientError;
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct RawFeedIdInput {
-    id: i32,
+pub struct RawFeedInput {
+    pub name: String,
+    pub url: String,
+    pub category: String,
+    #[serde(default)]
+    pub parse_overrides: ParseOverrides,
+    /// Omitted on update to keep the stored credentials, empty to clear them.
+    #[serde(default, skip_serializing)]
+    pub credentials: Option<FeedCredentials>,
+    /// Tags on top of the category, which is always tagged as well.
+    /// Omitted on update to keep the current ones.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Serialize, Debug, FromRow)]
@@ -19,14 +27,55 @@ pub struct RawFeedName {
     pub name: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, FromRow)]
+#[derive(Clone, Deserialize, Serialize, Debug, FromRow)]
 pub struct RawFeed {
     pub id: i32,
     pub name: String,
     pub url: String,
     pub category: String,
+    pub parse_overrides: Json<ParseOverrides>,
+    pub has_credentials: bool,
+    pub tags: Vec<String>,
+    pub active: bool,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl RawFeed {
+    /// Deleted, inactive and still paused feeds are neither fetched nor served.
+    /// The `feed_enabled` SQL function applies the same rule in queries.
+    pub fn is_enabled(&self) -> bool {
+        self.deleted_at.is_none()
+            && self.active
+            && self.paused_until.is_none_or(|until| until <= Utc::now())
+    }
+}
+
+/// Turns fetching off or back on. A pause ends on its own once `paused_until`
+/// has passed; clearing it resumes the feed right away.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FeedStatusInput {
+    pub active: bool,
+    pub paused_until: Option<DateTime<Utc>>,
 }
 
+/// Reads a `RawFeed` with its tags, to be followed by a `WHERE` clause.
+const RAW_FEED_SELECT: &str = "SELECT raw_feeds.id, raw_feeds.name, raw_feeds.url,
+        categories.name AS category,
+        raw_feeds.parse_overrides,
+        raw_feeds.credentials IS NOT NULL AS has_credentials,
+        raw_feeds.active, raw_feeds.paused_until, raw_feeds.deleted_at,
+        ARRAY(
+            SELECT tags.name FROM raw_feed_tags
+            JOIN tags ON tags.id = raw_feed_tags.tag_id
+            WHERE raw_feed_tags.feed_id = raw_feeds.id
+            ORDER BY tags.name
+        ) AS tags
+    FROM raw_feeds
+    INNER JOIN categories
+    ON
+    raw_feeds.category_id = categories.id";
+
 pub struct FeedDataSource {
     pool: PgPool,
 }
@@ -36,157 +85,943 @@ impl FeedDataSource {
         Self { pool }
     }
 
-    pub async fn get_categories(&self) -> Result<Vec<String>, anyhow::Error> {
-        let res = sqlx::query_scalar::<_, String>("SELECT name FROM categories ORDER BY name ASC;")
-            .fetch_all(&self.pool)
+    pub async fn get_tags(&self) -> Result<Vec<String>, anyhow::Error> {
+        let res = sqlx::query_scalar::<_, String>(
+            "SELECT DISTINCT tags.name
+            FROM tags
+            JOIN raw_feed_tags ON raw_feed_tags.tag_id = tags.id
+            JOIN raw_feeds ON raw_feeds.id = raw_feed_tags.feed_id
+            WHERE feed_enabled(raw_feeds)
+            ORDER BY tags.name ASC;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get tags from db")?;
+
+        Ok(res)
+    }
+
+    pub async fn get_raw_feeds(&self) -> Result<Vec<RawFeed>, anyhow::Error> {
+        let res = sqlx::query_as::<_, RawFeed>(&format!(
+            "{} WHERE raw_feeds.deleted_at IS NULL;",
+            RAW_FEED_SELECT
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get raw feeds from db")?;
+
+        Ok(res)
+    }
+
+    pub async fn get_raw_feed(&self, id: i32) -> Result<Option<RawFeed>, anyhow::Error> {
+        let mut conn = self
+            .pool
+            .acquire()
             .await
             .inspect_err(|e| {
                 eprintln!("Database error: {:?}", e);
             })
-            .context("Failed to get categories from db")?;
+            .context("Failed to acquire connection")?;
+
+        Self::fetch_raw_feed(&mut conn, id).await
+    }
+
+    /// Like `get_raw_feed`, inside a change's transaction.
+    pub async fn fetch_raw_feed(
+        conn: &mut PgConnection,
+        id: i32,
+    ) -> Result<Option<RawFeed>, anyhow::Error> {
+        let res = sqlx::query_as::<_, RawFeed>(&format!(
+            "{} WHERE raw_feeds.id = $1 AND raw_feeds.deleted_at IS NULL;",
+            RAW_FEED_SELECT
+        ))
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while getting feed: {}", id))?;
 
         Ok(res)
     }
 
-    pub async fn get_raw_feeds(&self) -> Result<Vec<RawFeed>, anyhow::Error> {
-        let res = sqlx::query_as::<_, RawFeed>(
-            "SELECT raw_feeds.id, raw_feeds.name, raw_feeds.url, categories.name AS category
-            FROM raw_feeds
-            INNER JOIN categories
-            ON
-            raw_feeds.category_id = categories.id;",
-        )
+    /// Feeds whose pause ended after `since` and that are enabled again.
+    pub async fn get_resumed_raw_feeds(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<RawFeed>, anyhow::Error> {
+        let res = sqlx::query_as::<_, RawFeed>(&format!(
+            "{} WHERE feed_enabled(raw_feeds) AND raw_feeds.paused_until > $1;",
+            RAW_FEED_SELECT
+        ))
+        .bind(since)
         .fetch_all(&self.pool)
         .await
         .inspect_err(|e| {
             eprintln!("Database error: {:?}", e);
         })
-        .context("Failed to get raw feeds from db")?;
+        .context("Failed to get resumed feeds from db")?;
 
         Ok(res)
     }
 
-    async fn fetch_category_id(&self, name: &str) -> Result<i32, anyhow::Error> {
-        let new_category_id = sqlx::query_scalar(
-            "INSERT INTO categories (name)
-            VALUES ($1)
-            ON CONFLICT (name) DO NOTHING
-            RETURNING id",
-        )
-        .bind(name)
+    pub async fn get_raw_feed_by_url(&self, url: &str) -> Result<Option<RawFeed>, anyhow::Error> {
+        let res = sqlx::query_as::<_, RawFeed>(&format!(
+            "{} WHERE raw_feeds.url = $1 AND raw_feeds.deleted_at IS NULL;",
+            RAW_FEED_SELECT
+        ))
+        .bind(url)
         .fetch_optional(&self.pool)
-        .await?;
-
-        let category_id: i32 = if let Some(id) = new_category_id {
-            id
-        } else {
-            sqlx::query_scalar("SELECT id FROM categories WHERE name = $1")
-                .bind(name)
-                .fetch_one(&self.pool)
-                .await
-                .inspect_err(|e| {
-                    eprintln!("Database error: {:?}", e);
-                })
-                .context("Failed to fetch existing category ID")?
-        };
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while getting feed: {}", url))?;
 
-        Ok(category_id)
+        Ok(res)
     }
 
-    pub async fn create_raw_feed(&self, input: RawFeedInput) -> Result<RawFeed, anyhow::Error> {
+    /// Creates a feed, storing its already encrypted `credentials` with it.
+    pub async fn create_raw_feed(
+        conn: &mut PgConnection,
+        input: RawFeedInput,
+        credentials: Option<Vec<u8>>,
+    ) -> Result<RawFeed, anyhow::Error> {
         println!("Creating new feed: {}", input.name);
 
-        let category_id = self.fetch_category_id(&input.category).await?;
+        let mut tx = conn
+            .begin()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to start transaction")?;
+
+        check_deleted_conflict(&mut tx, None, &input.name, &input.url).await?;
+        let category_id = fetch_category_id(&mut tx, &input.category).await?;
 
-        sqlx::query(
-            "INSERT INTO raw_feeds (name, url, category_id)
-                VALUES ($1, $2, $3)",
+        let id: i32 = sqlx::query_scalar(
+            "INSERT INTO raw_feeds (name, url, category_id, parse_overrides, credentials)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id",
         )
         .bind(&input.name)
         .bind(&input.url)
         .bind(category_id)
-        .execute(&self.pool)
+        .bind(Json(&input.parse_overrides))
+        .bind(credentials)
+        .fetch_one(&mut *tx)
         .await
         .inspect_err(|e| {
             eprintln!("Database error: {:?}", e);
         })
         .context("Failed to create new feed")?;
 
-        let res = sqlx::query_as::<_, RawFeed>(
-            "SELECT raw_feeds.id, raw_feeds.name, raw_feeds.url, categories.name AS category
-            FROM raw_feeds
-            INNER JOIN categories
-            ON
-            raw_feeds.category_id = categories.id
-            WHERE raw_feeds.name = $1;",
+        set_raw_feed_tags(
+            &mut tx,
+            id,
+            &input.category,
+            input.tags.as_deref().unwrap_or_default(),
         )
-        .bind(&input.name)
-        .fetch_one(&self.pool)
-        .await
-        .inspect_err(|e| {
-            eprintln!("Database error: {:?}", e);
-        })
-        .context(format!("Error while getting feed: {}", input.name))?;
+        .await?;
+
+        let res =
+            sqlx::query_as::<_, RawFeed>(&format!("{} WHERE raw_feeds.id = $1;", RAW_FEED_SELECT))
+                .bind(id)
+                .fetch_one(&mut *tx)
+                .await
+                .inspect_err(|e| {
+                    eprintln!("Database error: {:?}", e);
+                })
+                .context(format!("Error while getting feed: {}", input.name))?;
+
+        tx.commit()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to commit transaction")?;
 
         Ok(res)
     }
 
+    /// Feeds that fail to be created are skipped, each one is created in a
+    /// savepoint of its own.
     pub async fn batch_create_raw_feeds(
-        &self,
-        body: Vec<RawFeedInput>,
+        conn: &mut PgConnection,
+        body: Vec<(RawFeedInput, Option<Vec<u8>>)>,
     ) -> Result<Vec<RawFeed>, anyhow::Error> {
         let mut raw_feeds: Vec<RawFeed> = Vec::new();
-        for feed in body {
-            match self.create_raw_feed(feed).await {
-                Ok(feed) => raw_feeds.push(feed),
-                _ => {}
+        for (feed, credentials) in body {
+            if let Ok(feed) = Self::create_raw_feed(&mut *conn, feed, credentials).await {
+                raw_feeds.push(feed);
             }
         }
         Ok(raw_feeds)
     }
 
-    pub async fn update_raw_feed(&self, id: i32, body: RawFeedInput) -> Result<(), anyhow::Error> {
+    /// Updates a feed. `credentials` replaces the stored ones when given, with
+    /// `Some(None)` clearing them.
+    pub async fn update_raw_feed(
+        conn: &mut PgConnection,
+        id: i32,
+        body: RawFeedInput,
+        credentials: Option<Option<Vec<u8>>>,
+    ) -> Result<(), anyhow::Error> {
         println!("Updating feed: {}", &body.name);
 
-        let category_id = self.fetch_category_id(&body.category).await?;
+        let mut tx = conn
+            .begin()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to start transaction")?;
+
+        let current: Option<(String, Vec<String>)> = sqlx::query_as(
+            "SELECT raw_feeds.name,
+                ARRAY(
+                    SELECT tags.name FROM raw_feed_tags
+                    JOIN tags ON tags.id = raw_feed_tags.tag_id
+                    WHERE raw_feed_tags.feed_id = raw_feeds.id AND tags.name <> categories.name
+                ) AS extra_tags
+            FROM raw_feeds
+            INNER JOIN categories ON raw_feeds.category_id = categories.id
+            WHERE raw_feeds.id = $1 AND raw_feeds.deleted_at IS NULL
+            FOR UPDATE OF raw_feeds;",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while getting feed: {}", id))?;
+        let Some((old_name, extra_tags)) = current else {
+            return Err(ClientError::NotFound(format!("Unknown feed: {}", id)).into());
+        };
+
+        // Without new tags, the extra ones stay and the category tag moves along
+        let tags = body.tags.clone().unwrap_or(extra_tags);
+
+        check_deleted_conflict(&mut tx, Some(id), &body.name, &body.url).await?;
+        let category_id = fetch_category_id(&mut tx, &body.category).await?;
 
         sqlx::query(
             "UPDATE raw_feeds
-                SET name = $2, url = $3, category_id = $4
+                SET name = $2, url = $3, category_id = $4, parse_overrides = $5,
+                    credentials = CASE WHEN $6 THEN $7 ELSE credentials END
                 WHERE id = $1;",
         )
         .bind(id)
         .bind(&body.name)
         .bind(&body.url)
         .bind(category_id)
-        .execute(&self.pool)
+        .bind(Json(&body.parse_overrides))
+        .bind(credentials.is_some())
+        .bind(credentials.flatten())
+        .execute(&mut *tx)
         .await
         .inspect_err(|e| {
             eprintln!("Database error: {:?}", e);
         })
         .context(format!("Error while updating feed: {}", body.name))?;
 
+        // Cached feeds are keyed by name, so the cache follows a rename. A cache
+        // left behind under the new name by an earlier feed is dropped first,
+        // except for starred entries: they move to the renamed feed's cache, or
+        // keep the old cache around to become it when there is none.
+        if old_name != body.name {
+            sqlx::query(
+                "UPDATE cached_entries e
+                SET feed_id = renamed.id
+                FROM cached_feeds stale, cached_feeds renamed
+                WHERE stale.name = $2 AND renamed.name = $1
+                AND e.feed_id = stale.id
+                AND EXISTS (SELECT 1 FROM starred_entries s WHERE s.entry_id = e.id);",
+            )
+            .bind(&old_name)
+            .bind(&body.name)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!(
+                "Error while moving starred entries to: {}",
+                body.name
+            ))?;
+
+            sqlx::query(
+                "DELETE FROM cached_entries e
+                USING cached_feeds stale
+                WHERE stale.name = $1 AND e.feed_id = stale.id
+                AND NOT EXISTS (SELECT 1 FROM starred_entries s WHERE s.entry_id = e.id);",
+            )
+            .bind(&body.name)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!(
+                "Error while dropping stale cache of: {}",
+                body.name
+            ))?;
+
+            sqlx::query(
+                "DELETE FROM cached_feeds stale
+                WHERE name = $1
+                AND NOT EXISTS (SELECT 1 FROM cached_entries e WHERE e.feed_id = stale.id);",
+            )
+            .bind(&body.name)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!(
+                "Error while dropping stale cache of: {}",
+                body.name
+            ))?;
+        }
+
+        sqlx::query("UPDATE cached_feeds SET name = $2, category_id = $3 WHERE name IN ($1, $2);")
+            .bind(&old_name)
+            .bind(&body.name)
+            .bind(category_id)
+            .execute(&mut *tx)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!("Error while renaming cached feed: {}", old_name))?;
+
+        set_raw_feed_tags(&mut tx, id, &body.category, &tags).await?;
+
+        tx.commit()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to commit transaction")?;
+
         Ok(())
     }
 
-    pub async fn delete_raw_feed(&self, id: i32) -> Result<(), anyhow::Error> {
+    pub async fn get_feed_credentials(
+        conn: &mut PgConnection,
+        id: i32,
+    ) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let res = sqlx::query_scalar::<_, Option<Vec<u8>>>(
+            "SELECT credentials FROM raw_feeds WHERE id = $1;",
+        )
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while getting credentials for feed: {}", id))?;
+
+        Ok(res.flatten())
+    }
+
+    pub async fn set_raw_feed_status(
+        conn: &mut PgConnection,
+        id: i32,
+        status: FeedStatusInput,
+    ) -> Result<(), anyhow::Error> {
+        let res = sqlx::query(
+            "UPDATE raw_feeds SET active = $2, paused_until = $3
+            WHERE id = $1 AND deleted_at IS NULL;",
+        )
+        .bind(id)
+        .bind(status.active)
+        .bind(status.paused_until)
+        .execute(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while updating status of feed: {}", id))?;
+
+        if res.rows_affected() == 0 {
+            return Err(ClientError::NotFound(format!("Unknown feed: {}", id)).into());
+        }
+
+        Ok(())
+    }
+
+    /// Hides the feed until it is restored or purged. Its cached entries are
+    /// kept so a restore brings it back as it was, and its name and URL stay
+    /// taken.
+    pub async fn delete_raw_feed(conn: &mut PgConnection, id: i32) -> Result<(), anyhow::Error> {
         let res = sqlx::query_as::<_, RawFeedName>(
-            "WITH deleted_row as (
-                DELETE FROM raw_feeds WHERE id = $1
-                RETURNING name
-            )
-            SELECT name FROM deleted_row;",
+            "UPDATE raw_feeds SET deleted_at = NOW()
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING name;",
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_optional(conn)
         .await
         .inspect_err(|e| {
             eprintln!("Database error: {:?}", e);
         })
-        .context(format!("Error while deleting raw feed: {}", id))?;
+        .context(format!("Error while deleting raw feed: {}", id))?
+        .ok_or_else(|| ClientError::NotFound(format!("Unknown feed: {}", id)))?;
 
         println!("Successfully deleted feed: {}", res.name);
 
         Ok(())
     }
+
+    pub async fn get_deleted_raw_feeds(&self) -> Result<Vec<RawFeed>, anyhow::Error> {
+        let res = sqlx::query_as::<_, RawFeed>(&format!(
+            "{} WHERE raw_feeds.deleted_at IS NOT NULL
+            ORDER BY raw_feeds.deleted_at DESC;",
+            RAW_FEED_SELECT
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get deleted raw feeds from db")?;
+
+        Ok(res)
+    }
+
+    pub async fn restore_raw_feed(conn: &mut PgConnection, id: i32) -> Result<(), anyhow::Error> {
+        let res = sqlx::query_as::<_, RawFeedName>(
+            "UPDATE raw_feeds SET deleted_at = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            RETURNING name;",
+        )
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while restoring raw feed: {}", id))?
+        .ok_or_else(|| ClientError::NotFound(format!("No deleted feed: {}", id)))?;
+
+        println!("Successfully restored feed: {}", res.name);
+
+        Ok(())
+    }
+
+    /// Removes feeds deleted more than `grace_days` ago for good, along with
+    /// their cached feeds. Starred entries stay, like in a cache clear.
+    pub async fn purge_deleted_raw_feeds(&self, grace_days: i32) -> Result<u64, anyhow::Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to start transaction")?;
+
+        let purged: Vec<String> = sqlx::query_scalar(
+            "DELETE FROM raw_feeds
+            WHERE deleted_at < NOW() - make_interval(days => $1)
+            RETURNING name;",
+        )
+        .bind(grace_days)
+        .fetch_all(&mut *tx)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to purge deleted feeds")?;
+
+        sqlx::query(
+            "DELETE FROM cached_entries e
+            USING cached_feeds cached
+            WHERE cached.id = e.feed_id
+            AND cached.name = ANY($1)
+            AND NOT EXISTS (SELECT 1 FROM starred_entries s WHERE s.entry_id = e.id);",
+        )
+        .bind(&purged)
+        .execute(&mut *tx)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to purge cached entries of deleted feeds")?;
+
+        sqlx::query(
+            "DELETE FROM cached_feeds cached
+            WHERE name = ANY($1)
+            AND NOT EXISTS (SELECT 1 FROM cached_entries e WHERE e.feed_id = cached.id);",
+        )
+        .bind(&purged)
+        .execute(&mut *tx)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to purge cached deleted feeds")?;
+
+        tx.commit()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to commit transaction")?;
+
+        Ok(purged.len() as u64)
+    }
+}
+
+/// Deleted feeds keep their name and URL until they are purged, so a new
+/// feed can't take them over along with their cache. `id` is the feed being
+/// updated, if any.
+async fn check_deleted_conflict(
+    conn: &mut PgConnection,
+    id: Option<i32>,
+    name: &str,
+    url: &str,
+) -> Result<(), anyhow::Error> {
+    let deleted: Option<i32> = sqlx::query_scalar(
+        "SELECT id FROM raw_feeds
+        WHERE deleted_at IS NOT NULL AND (name = $2 OR url = $3)
+        AND id IS DISTINCT FROM $1
+        LIMIT 1;",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(url)
+    .fetch_optional(&mut *conn)
+    .await
+    .inspect_err(|e| {
+        eprintln!("Database error: {:?}", e);
+    })
+    .context("Failed to check deleted feeds")?;
+
+    match deleted {
+        Some(deleted) => Err(ClientError::Conflict(format!(
+            "Deleted feed {} already uses this name or URL, restore it instead",
+            deleted
+        ))
+        .into()),
+        None => Ok(()),
+    }
+}
+
+async fn fetch_category_id(conn: &mut PgConnection, name: &str) -> Result<i32, anyhow::Error> {
+    let new_category_id = sqlx::query_scalar(
+        "INSERT INTO categories (name)
+        VALUES ($1)
+        ON CONFLICT (name) DO NOTHING
+        RETURNING id",
+    )
+    .bind(name)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let category_id: i32 = if let Some(id) = new_category_id {
+        id
+    } else {
+        sqlx::query_scalar("SELECT id FROM categories WHERE name = $1")
+            .bind(name)
+            .fetch_one(&mut *conn)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to fetch existing category ID")?
+    };
+
+    Ok(category_id)
+}
+
+/// Replaces the tags of a feed with `tags` plus its category. Links are
+/// dropped before tags are created, so the orphaned tags cleanup never sees
+/// a tag that is about to be linked.
+async fn set_raw_feed_tags(
+    conn: &mut PgConnection,
+    id: i32,
+    category: &str,
+    tags: &[String],
+) -> Result<(), anyhow::Error> {
+    let mut names: Vec<&str> = tags
+        .iter()
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    names.push(category);
+    names.sort_unstable();
+    names.dedup();
+
+    sqlx::query(
+        "DELETE FROM raw_feed_tags rt
+        USING tags t
+        WHERE rt.tag_id = t.id AND rt.feed_id = $1 AND t.name <> ALL($2);",
+    )
+    .bind(id)
+    .bind(&names)
+    .execute(&mut *conn)
+    .await
+    .inspect_err(|e| {
+        eprintln!("Database error: {:?}", e);
+    })
+    .context(format!("Failed to clear tags of feed: {}", id))?;
+
+    sqlx::query(
+        "INSERT INTO tags (name)
+        SELECT unnest($1::varchar[])
+        ON CONFLICT (name) DO NOTHING;",
+    )
+    .bind(&names)
+    .execute(&mut *conn)
+    .await
+    .inspect_err(|e| {
+        eprintln!("Database error: {:?}", e);
+    })
+    .context("Failed to create tags")?;
+
+    sqlx::query(
+        "INSERT INTO raw_feed_tags (feed_id, tag_id)
+        SELECT $1, id FROM tags WHERE name = ANY($2)
+        ON CONFLICT DO NOTHING;",
+    )
+    .bind(id)
+    .bind(&names)
+    .execute(&mut *conn)
+    .await
+    .inspect_err(|e| {
+        eprintln!("Database error: {:?}", e);
+    })
+    .context(format!("Failed to tag feed: {}", id))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::data::ScratchDatabase;
+
+    fn raw_feed(active: bool, paused_until: Option<DateTime<Utc>>) -> RawFeed {
+        RawFeed {
+            id: 1,
+            name: "feed".to_string(),
+            url: "https://example.com/rss".to_string(),
+            category: "news".to_string(),
+            parse_overrides: Json(ParseOverrides::default()),
+            has_credentials: false,
+            tags: Vec::new(),
+            active,
+            paused_until,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_is_enabled() {
+        let now = Utc::now();
+        assert!(raw_feed(true, None).is_enabled());
+        assert!(!raw_feed(false, None).is_enabled());
+        assert!(!raw_feed(true, Some(now + TimeDelta::hours(1))).is_enabled());
+        assert!(raw_feed(true, Some(now - TimeDelta::hours(1))).is_enabled());
+        assert!(!raw_feed(false, Some(now - TimeDelta::hours(1))).is_enabled());
+
+        let deleted = RawFeed {
+            deleted_at: Some(now),
+            ..raw_feed(true, None)
+        };
+        assert!(!deleted.is_enabled());
+    }
+
+    fn input(name: &str, url: &str) -> RawFeedInput {
+        RawFeedInput {
+            name: name.to_string(),
+            url: url.to_string(),
+            category: "news".to_string(),
+            parse_overrides: ParseOverrides::default(),
+            credentials: None,
+            tags: Some(vec!["rust".to_string()]),
+        }
+    }
+
+    fn is_conflict(err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<ClientError>(),
+                Some(ClientError::Conflict(_))
+            )
+        })
+    }
+
+    fn is_not_found(err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<ClientError>(),
+                Some(ClientError::NotFound(_))
+            )
+        })
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_restore_deleted_feed() {
+        let database = ScratchDatabase::new("test_restore_deleted_feed").await;
+        let datasource = FeedDataSource::new(database.pool.clone());
+        let mut conn = database.pool.acquire().await.unwrap();
+        let feed = FeedDataSource::create_raw_feed(
+            &mut conn,
+            input("feed", "https://example.com/rss"),
+            None,
+        )
+        .await
+        .unwrap();
+
+        FeedDataSource::delete_raw_feed(&mut conn, feed.id)
+            .await
+            .unwrap();
+        assert!(datasource.get_raw_feed(feed.id).await.unwrap().is_none());
+        assert_eq!(datasource.get_deleted_raw_feeds().await.unwrap().len(), 1);
+
+        // A deleted feed can only be restored
+        let err = FeedDataSource::delete_raw_feed(&mut conn, feed.id)
+            .await
+            .unwrap_err();
+        assert!(is_not_found(&err));
+        let err = FeedDataSource::update_raw_feed(
+            &mut conn,
+            feed.id,
+            input("feed", "https://example.com/rss"),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(is_not_found(&err));
+        let status = FeedStatusInput {
+            active: false,
+            paused_until: None,
+        };
+        let err = FeedDataSource::set_raw_feed_status(&mut conn, feed.id, status)
+            .await
+            .unwrap_err();
+        assert!(is_not_found(&err));
+
+        // Name and URL stay taken while the feed can still be restored
+        let err = FeedDataSource::create_raw_feed(
+            &mut conn,
+            input("other", "https://example.com/rss"),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(is_conflict(&err));
+        let err = FeedDataSource::create_raw_feed(
+            &mut conn,
+            input("feed", "https://example.com/other"),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(is_conflict(&err));
+
+        FeedDataSource::restore_raw_feed(&mut conn, feed.id)
+            .await
+            .unwrap();
+        let restored = datasource.get_raw_feed(feed.id).await.unwrap().unwrap();
+        assert_eq!(restored.tags, vec!["news", "rust"]);
+        assert!(datasource.get_deleted_raw_feeds().await.unwrap().is_empty());
+
+        let err = FeedDataSource::restore_raw_feed(&mut conn, feed.id)
+            .await
+            .unwrap_err();
+        assert!(is_not_found(&err));
+
+        drop(conn);
+        database.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_purge_deleted_feeds() {
+        let database = ScratchDatabase::new("test_purge_deleted_feeds").await;
+        let pool = &database.pool;
+        let datasource = FeedDataSource::new(pool.clone());
+        let mut conn = pool.acquire().await.unwrap();
+        let mut ids = Vec::new();
+        for name in ["old", "starred", "recent", "kept"] {
+            let url = format!("https://example.com/{}", name);
+            let feed = FeedDataSource::create_raw_feed(&mut conn, input(name, &url), None)
+                .await
+                .unwrap();
+            sqlx::query(
+                "WITH cached AS (
+                    INSERT INTO cached_feeds (name, category_id)
+                    SELECT name, category_id FROM raw_feeds WHERE id = $1
+                    RETURNING id
+                )
+                INSERT INTO cached_entries (feed_id, title, url, created_date)
+                SELECT cached.id, 'entry', $2, NOW() FROM cached;",
+            )
+            .bind(feed.id)
+            .bind(format!("{}/entry", url))
+            .execute(pool)
+            .await
+            .unwrap();
+            ids.push(feed.id);
+        }
+        sqlx::query(
+            "WITH reader AS (INSERT INTO users (github_id) VALUES (1) RETURNING id)
+            INSERT INTO starred_entries (user_id, entry_id)
+            SELECT reader.id, e.id FROM reader, cached_entries e
+            WHERE e.url = 'https://example.com/starred/entry';",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        for id in &ids[..3] {
+            FeedDataSource::delete_raw_feed(&mut conn, *id)
+                .await
+                .unwrap();
+        }
+        sqlx::query(
+            "UPDATE raw_feeds SET deleted_at = NOW() - INTERVAL '31 days' WHERE id = ANY($1);",
+        )
+        .bind(&ids[..2])
+        .execute(pool)
+        .await
+        .unwrap();
+
+        assert_eq!(datasource.purge_deleted_raw_feeds(30).await.unwrap(), 2);
+
+        let raw_feeds: Vec<String> = sqlx::query_scalar("SELECT name FROM raw_feeds ORDER BY name")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+        assert_eq!(raw_feeds, vec!["kept", "recent"]);
+        // The starred entry keeps its cached feed around
+        let cached_feeds: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM cached_feeds ORDER BY name")
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        assert_eq!(cached_feeds, vec!["kept", "recent", "starred"]);
+        let entries: i64 = sqlx::query_scalar("SELECT count(*) FROM cached_entries")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(entries, 3);
+
+        // A purged feed's name and URL are free again
+        FeedDataSource::create_raw_feed(&mut conn, input("old", "https://example.com/old"), None)
+            .await
+            .unwrap();
+
+        drop(conn);
+        database.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_rename_keeps_starred_entries() {
+        let database = ScratchDatabase::new("test_rename_keeps_starred_entries").await;
+        let pool = &database.pool;
+        let mut conn = pool.acquire().await.unwrap();
+        let cached = FeedDataSource::create_raw_feed(
+            &mut conn,
+            input("cached", "https://example.com/cached"),
+            None,
+        )
+        .await
+        .unwrap();
+        let uncached = FeedDataSource::create_raw_feed(
+            &mut conn,
+            input("uncached", "https://example.com/uncached"),
+            None,
+        )
+        .await
+        .unwrap();
+        // Caches left behind by purged feeds, each holding a starred and a plain entry
+        sqlx::query(
+            "WITH stale AS (
+                INSERT INTO cached_feeds (name, category_id)
+                SELECT name, category_id FROM raw_feeds WHERE id = $1
+                UNION ALL
+                SELECT unnest(ARRAY['stale-a', 'stale-b']), category_id FROM raw_feeds WHERE id = $1
+                RETURNING id, name
+            )
+            INSERT INTO cached_entries (feed_id, title, url, created_date)
+            SELECT stale.id, 'entry', 'https://example.com/' || stale.name || '/' || e, NOW()
+            FROM stale, unnest(ARRAY['starred', 'plain']) e;",
+        )
+        .bind(cached.id)
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "WITH reader AS (INSERT INTO users (github_id) VALUES (1) RETURNING id)
+            INSERT INTO starred_entries (user_id, entry_id)
+            SELECT reader.id, e.id FROM reader, cached_entries e
+            WHERE e.url LIKE '%/starred';",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        FeedDataSource::update_raw_feed(
+            &mut conn,
+            cached.id,
+            input("stale-a", "https://example.com/cached"),
+            None,
+        )
+        .await
+        .unwrap();
+        FeedDataSource::update_raw_feed(
+            &mut conn,
+            uncached.id,
+            input("stale-b", "https://example.com/uncached"),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let entries: Vec<(String, String)> = sqlx::query_as(
+            "SELECT cached.name, e.url
+            FROM cached_entries e
+            JOIN cached_feeds cached ON cached.id = e.feed_id
+            ORDER BY e.url;",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        let entry =
+            |feed: &str, url: &str| (feed.to_string(), format!("https://example.com/{}", url));
+        assert_eq!(
+            entries,
+            vec![
+                entry("stale-a", "cached/plain"),
+                entry("stale-a", "cached/starred"),
+                entry("stale-a", "stale-a/starred"),
+                entry("stale-b", "stale-b/starred"),
+            ]
+        );
+        let starred: i64 = sqlx::query_scalar("SELECT count(*) FROM starred_entries")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(starred, 3);
+
+        drop(conn);
+        database.drop().await;
+    }
 }
@@ -0,0 +1,110 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool};
+
+#[derive(Debug)]
+pub struct FetchLogInput {
+    pub feed_id: i32,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i32,
+    pub http_status: Option<i32>,
+    pub bytes: Option<i32>,
+    pub entries_parsed: Option<i32>,
+    pub new_entries: Option<i32>,
+    pub error: Option<String>,
+    pub error_kind: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, FromRow)]
+pub struct FetchLog {
+    pub id: i32,
+    pub feed_id: i32,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i32,
+    pub http_status: Option<i32>,
+    pub bytes: Option<i32>,
+    pub entries_parsed: Option<i32>,
+    pub new_entries: Option<i32>,
+    pub error: Option<String>,
+    pub error_kind: Option<String>,
+}
+
+pub struct FetchLogDataSource {
+    pool: PgPool,
+}
+
+impl FetchLogDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Logs a fetch on the connection holding the feed's refresh lock.
+    pub async fn record(
+        conn: &mut PgConnection,
+        input: FetchLogInput,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            "INSERT INTO fetch_log
+                (feed_id, started_at, duration_ms, http_status, bytes, entries_parsed, new_entries, error, error_kind)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(input.feed_id)
+        .bind(input.started_at)
+        .bind(input.duration_ms)
+        .bind(input.http_status)
+        .bind(input.bytes)
+        .bind(input.entries_parsed)
+        .bind(input.new_entries)
+        .bind(&input.error)
+        .bind(&input.error_kind)
+        .execute(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to record fetch for feed: {}", input.feed_id))?;
+
+        Ok(())
+    }
+
+    pub async fn get_fetch_logs(
+        &self,
+        feed_id: i32,
+        limit: i64,
+    ) -> Result<Vec<FetchLog>, anyhow::Error> {
+        let res = sqlx::query_as::<_, FetchLog>(
+            "SELECT id, feed_id, started_at, duration_ms, http_status, bytes, entries_parsed, new_entries, error, error_kind
+            FROM fetch_log
+            WHERE feed_id = $1
+            ORDER BY started_at DESC
+            LIMIT $2;",
+        )
+        .bind(feed_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to get fetch log for feed: {}", feed_id))?;
+
+        Ok(res)
+    }
+
+    pub async fn prune(&self, retention_days: i32) -> Result<u64, anyhow::Error> {
+        let res = sqlx::query(
+            "DELETE FROM fetch_log
+            WHERE started_at < NOW() - make_interval(days => $1);",
+        )
+        .bind(retention_days)
+        .execute(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to prune fetch log")?;
+
+        Ok(res.rows_affected())
+    }
+}
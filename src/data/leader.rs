@@ -0,0 +1,95 @@
+use anyhow::Context;
+use sqlx::{Connection, PgConnection, PgPool};
+
+/// Keys of the advisory lock held by the instance running the scheduler.
+const LEADER_LOCK_CLASS: i32 = 2;
+const SCHEDULER_LOCK_ID: i32 = 0;
+
+/// A session-level advisory lock, held for as long as its connection stays open.
+pub struct LeaderLock {
+    conn: PgConnection,
+}
+
+impl LeaderLock {
+    /// Losing the connection releases the lock on the server, so a dead
+    /// connection means another instance may already have taken over.
+    pub async fn is_held(&mut self) -> bool {
+        self.conn.ping().await.is_ok()
+    }
+}
+
+pub struct LeaderDataSource {
+    pool: PgPool,
+}
+
+impl LeaderDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Takes the scheduler lock without waiting, on a connection that is
+    /// detached from the pool so it is never handed to anyone else.
+    pub async fn try_acquire(&self) -> Result<Option<LeaderLock>, anyhow::Error> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to acquire connection")?
+            .detach();
+
+        let acquired: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1, $2);")
+            .bind(LEADER_LOCK_CLASS)
+            .bind(SCHEDULER_LOCK_ID)
+            .fetch_one(&mut conn)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to try leader lock")?;
+
+        if !acquired {
+            let _ = conn.close().await;
+            return Ok(None);
+        }
+
+        Ok(Some(LeaderLock { conn }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::{self, Duration};
+
+    use super::*;
+    use crate::data::{ScratchDatabase, SCHEDULER_LOCK};
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_one_leader_at_a_time() {
+        let _scheduler = SCHEDULER_LOCK.lock().await;
+        let database = ScratchDatabase::new("test_one_leader_at_a_time").await;
+        let leader = LeaderDataSource::new(database.pool.clone());
+
+        let mut lock = leader.try_acquire().await.unwrap().unwrap();
+        assert!(lock.is_held().await);
+        assert!(leader.try_acquire().await.unwrap().is_none());
+
+        // Once the leader's connection goes away, another instance takes over
+        drop(lock);
+        let mut next = None;
+        for _ in 0..100 {
+            next = leader.try_acquire().await.unwrap();
+            if next.is_some() {
+                break;
+            }
+            time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(next.is_some());
+        drop(next);
+
+        database.drop().await;
+    }
+}
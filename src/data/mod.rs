@@ -1,9 +1,43 @@
 mod atom;
+mod audit;
 mod cache;
+mod categories;
+mod credentials;
 mod feeds;
+mod fetch_log;
+mod leader;
+mod notify;
+mod opml;
+mod quirks;
+mod read_state;
+mod retention;
 mod rss;
+#[cfg(test)]
+mod scratch;
+mod search;
+mod starred;
+mod subscriptions;
+mod url_policy;
+mod users;
 mod xml;
 
+pub use audit::*;
 pub use cache::*;
+pub use categories::*;
+pub use credentials::*;
 pub use feeds::*;
+pub use fetch_log::*;
+pub use leader::*;
+pub use notify::*;
+pub use opml::*;
+pub use quirks::*;
+pub use read_state::*;
+pub use retention::*;
+#[cfg(test)]
+pub use scratch::*;
+pub use search::*;
+pub use starred::*;
+pub use subscriptions::*;
+pub use url_policy::*;
+pub use users::*;
 pub use xml::*;
@@ -0,0 +1,46 @@
+use anyhow::Context;
+use sqlx::{postgres::PgListener, PgPool};
+
+/// Every instance listens here and drops its in-memory responses on a notification.
+const INVALIDATION_CHANNEL: &str = "cache_invalidated";
+
+pub struct NotifyDataSource {
+    pool: PgPool,
+}
+
+impl NotifyDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn notify_invalidation(&self) -> Result<(), anyhow::Error> {
+        sqlx::query("SELECT pg_notify($1, '');")
+            .bind(INVALIDATION_CHANNEL)
+            .execute(&self.pool)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to notify cache invalidation")?;
+
+        Ok(())
+    }
+
+    pub async fn listen_invalidations(&self) -> Result<PgListener, anyhow::Error> {
+        let mut listener = PgListener::connect_with(&self.pool)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to open listener connection")?;
+        listener
+            .listen(INVALIDATION_CHANNEL)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to listen for cache invalidations")?;
+
+        Ok(listener)
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use chrono::Utc;
+use quickxml_to_serde::{xml_string_to_json, Config};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::RawFeed;
+
+/// Feeds outside of any folder are filed here.
+pub const DEFAULT_OPML_CATEGORY: &str = "Uncategorized";
+
+/// A feed read from an OPML outline, filed under its innermost folder.
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+pub struct OpmlFeed {
+    pub name: String,
+    pub url: String,
+    pub category: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct InvalidOutline {
+    pub text: String,
+    pub reason: String,
+}
+
+#[derive(Default, Deserialize, Serialize, Debug)]
+pub struct OpmlDocument {
+    pub feeds: Vec<OpmlFeed>,
+    pub invalid: Vec<InvalidOutline>,
+}
+
+#[derive(Default, Deserialize, Serialize, Debug)]
+pub struct OpmlImportReport {
+    pub created: Vec<RawFeed>,
+    pub skipped: Vec<OpmlFeed>,
+    pub invalid: Vec<InvalidOutline>,
+}
+
+/// Reads an OPML 1.0 or 2.0 subscription list. Outlines with an `xmlUrl` are
+/// feeds, outlines with nested outlines are folders, anything else is invalid.
+pub fn parse_opml(xml_string: &str) -> Result<OpmlDocument, anyhow::Error> {
+    let value = xml_string_to_json(xml_string.into(), &Config::new_with_defaults())
+        .context("Failed to parse OPML document")?;
+    let body = value
+        .get("opml")
+        .and_then(|opml| opml.get("body"))
+        .context("Not an OPML document")?;
+
+    let mut document = OpmlDocument::default();
+    for outline in children(body) {
+        read_outline(outline, None, &mut document);
+    }
+
+    Ok(document)
+}
+
+/// Writes feeds as an OPML 2.0 document with one folder per category, both
+/// sorted by name.
+pub fn write_opml(title: &str, feeds: &[OpmlFeed]) -> String {
+    let mut categories: BTreeMap<&str, Vec<&OpmlFeed>> = BTreeMap::new();
+    for feed in feeds {
+        categories.entry(&feed.category).or_default().push(feed);
+    }
+
+    let mut opml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    opml += "<opml version=\"2.0\">\n";
+    opml += "  <head>\n";
+    opml += &format!("    <title>{}</title>\n", escape(title));
+    opml += &format!(
+        "    <dateCreated>{}</dateCreated>\n",
+        Utc::now().to_rfc2822()
+    );
+    opml += "  </head>\n";
+    opml += "  <body>\n";
+    for (category, mut feeds) in categories {
+        feeds.sort_by(|a, b| a.name.cmp(&b.name));
+        let category = escape(category);
+        opml += &format!(
+            "    <outline text=\"{}\" title=\"{}\">\n",
+            category, category
+        );
+        for feed in feeds {
+            let name = escape(&feed.name);
+            opml += &format!(
+                "      <outline type=\"rss\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\"/>\n",
+                name,
+                name,
+                escape(&feed.url)
+            );
+        }
+        opml += "    </outline>\n";
+    }
+    opml += "  </body>\n";
+    opml += "</opml>\n";
+
+    opml
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn read_outline(outline: &Value, folder: Option<&str>, document: &mut OpmlDocument) {
+    let text = attribute(outline, "title").or_else(|| attribute(outline, "text"));
+
+    if let Some(url) = attribute(outline, "xmlUrl") {
+        let valid = Url::parse(&url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+        if !valid {
+            document.invalid.push(InvalidOutline {
+                text: text.unwrap_or(url),
+                reason: "xmlUrl is not an http(s) URL".to_string(),
+            });
+            return;
+        }
+
+        document.feeds.push(OpmlFeed {
+            name: text.unwrap_or_else(|| url.clone()),
+            url,
+            category: folder.unwrap_or(DEFAULT_OPML_CATEGORY).to_string(),
+        });
+        return;
+    }
+
+    let nested = children(outline);
+    if nested.is_empty() {
+        document.invalid.push(InvalidOutline {
+            text: text.unwrap_or_default(),
+            reason: "Outline has neither an xmlUrl nor nested outlines".to_string(),
+        });
+        return;
+    }
+
+    // An unnamed folder keeps its feeds in the enclosing one
+    let folder = text.as_deref().or(folder);
+    for outline in nested {
+        read_outline(outline, folder, document);
+    }
+}
+
+fn children(value: &Value) -> Vec<&Value> {
+    match value.get("outline") {
+        Some(Value::Array(outlines)) => outlines.iter().collect(),
+        Some(outline @ Value::Object(_)) => vec![outline],
+        _ => Vec::new(),
+    }
+}
+
+/// Attribute names are matched case-insensitively, some exporters write `xmlurl`.
+fn attribute(outline: &Value, name: &str) -> Option<String> {
+    let (_, value) = outline.as_object()?.iter().find(|(key, _)| {
+        key.strip_prefix('@')
+            .is_some_and(|key| key.eq_ignore_ascii_case(name))
+    })?;
+
+    let text = match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::Bool(flag) => flag.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nested_folders() {
+        let document = parse_opml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <opml version="2.0">
+              <head><title>Subscriptions</title></head>
+              <body>
+                <outline text="Code" title="Code">
+                  <outline type="rss" text="Rust Blog" xmlUrl="https://blog.rust-lang.org/feed.xml"/>
+                  <outline text="Databases">
+                    <outline type="rss" text="Postgres &amp; Friends" xmlUrl="https://postgres.example.com/rss"/>
+                  </outline>
+                </outline>
+                <outline type="rss" text="Loose" xmlUrl="https://loose.example.com/atom.xml"/>
+              </body>
+            </opml>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            document.feeds,
+            vec![
+                OpmlFeed {
+                    name: "Rust Blog".to_string(),
+                    url: "https://blog.rust-lang.org/feed.xml".to_string(),
+                    category: "Code".to_string(),
+                },
+                OpmlFeed {
+                    name: "Postgres & Friends".to_string(),
+                    url: "https://postgres.example.com/rss".to_string(),
+                    category: "Databases".to_string(),
+                },
+                OpmlFeed {
+                    name: "Loose".to_string(),
+                    url: "https://loose.example.com/atom.xml".to_string(),
+                    category: DEFAULT_OPML_CATEGORY.to_string(),
+                },
+            ]
+        );
+        assert!(document.invalid.is_empty());
+    }
+
+    #[test]
+    fn test_parse_reports_invalid_outlines() {
+        let document = parse_opml(
+            r#"<opml version="1.0">
+              <body>
+                <outline text="Not a feed"/>
+                <outline text="Local file" xmlUrl="file:///etc/passwd"/>
+                <outline text="Lowercase" xmlurl="https://lower.example.com/rss"/>
+              </body>
+            </opml>"#,
+        )
+        .unwrap();
+
+        assert_eq!(document.feeds.len(), 1);
+        assert_eq!(document.feeds[0].url, "https://lower.example.com/rss");
+        let invalid: Vec<&str> = document.invalid.iter().map(|o| o.text.as_str()).collect();
+        assert_eq!(invalid, vec!["Not a feed", "Local file"]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let feeds = vec![
+            OpmlFeed {
+                name: "Rust Blog".to_string(),
+                url: "https://blog.rust-lang.org/feed.xml".to_string(),
+                category: "Code".to_string(),
+            },
+            OpmlFeed {
+                name: "\"Quotes\" & <Brackets>".to_string(),
+                url: "https://example.com/rss?a=1&b='2'".to_string(),
+                category: "News & Views".to_string(),
+            },
+            OpmlFeed {
+                name: "Another".to_string(),
+                url: "https://another.example.com/atom.xml".to_string(),
+                category: "Code".to_string(),
+            },
+        ];
+
+        let document = parse_opml(&write_opml("Subscriptions", &feeds)).unwrap();
+
+        let mut expected = feeds.clone();
+        expected.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+        assert_eq!(document.feeds, expected);
+        assert!(document.invalid.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip_single_feed() {
+        let feeds = vec![OpmlFeed {
+            name: "Only".to_string(),
+            url: "https://only.example.com/rss".to_string(),
+            category: DEFAULT_OPML_CATEGORY.to_string(),
+        }];
+
+        let document = parse_opml(&write_opml("Subscriptions", &feeds)).unwrap();
+
+        assert_eq!(document.feeds, feeds);
+    }
+
+    #[test]
+    fn test_parse_rejects_other_documents() {
+        assert!(parse_opml("<rss><channel></channel></rss>").is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::{atom::parse_atom_date, rss::parse_rss_date};
+
+/// Built-in override sets for feed quirks we keep running into.
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuirkProfile {
+    /// Atom feeds whose entry links carry a `rel` but no `type`.
+    AtomAlternateLink,
+    /// Podcast feeds that mix dated episodes with undated trailers.
+    UndatedItems,
+}
+
+/// Per-feed parsing overrides, applied to the raw XML-as-JSON before the
+/// shared RSS and Atom deserializers see it. Explicit fields win over the profile.
+#[derive(Clone, Default, Deserialize, Serialize, Debug, PartialEq)]
+pub struct ParseOverrides {
+    pub profile: Option<QuirkProfile>,
+    pub link_rel: Option<String>,
+    pub link_type: Option<String>,
+    pub date_format: Option<String>,
+    /// Element to read the title from, e.g. `description` or `title[1]`.
+    pub title_element: Option<String>,
+    #[serde(default)]
+    pub ignore_undated: bool,
+}
+
+#[derive(Clone, Copy)]
+pub enum FeedSyntax {
+    Rss,
+    Atom,
+}
+
+impl FeedSyntax {
+    fn date_fields(&self) -> &'static [&'static str] {
+        match self {
+            FeedSyntax::Rss => &["pubDate"],
+            FeedSyntax::Atom => &["published", "updated"],
+        }
+    }
+
+    fn parse_date(&self, s: &str) -> Option<DateTime<Utc>> {
+        match self {
+            FeedSyntax::Rss => parse_rss_date(s),
+            FeedSyntax::Atom => parse_atom_date(s),
+        }
+    }
+
+    fn format_date(&self, date: DateTime<Utc>) -> String {
+        match self {
+            FeedSyntax::Rss => date.to_rfc2822(),
+            FeedSyntax::Atom => date.to_rfc3339(),
+        }
+    }
+
+    fn entries<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        match self {
+            FeedSyntax::Rss => value.pointer_mut("/rss/channel/item"),
+            FeedSyntax::Atom => value.pointer_mut("/feed/entry"),
+        }
+    }
+}
+
+impl ParseOverrides {
+    fn resolved(&self) -> ParseOverrides {
+        let mut resolved = match self.profile {
+            Some(QuirkProfile::AtomAlternateLink) => ParseOverrides {
+                link_rel: Some("alternate".to_string()),
+                ..Default::default()
+            },
+            Some(QuirkProfile::UndatedItems) => ParseOverrides {
+                ignore_undated: true,
+                ..Default::default()
+            },
+            None => ParseOverrides::default(),
+        };
+
+        if self.link_rel.is_some() {
+            resolved.link_rel = self.link_rel.clone();
+        }
+        if self.link_type.is_some() {
+            resolved.link_type = self.link_type.clone();
+        }
+        if self.date_format.is_some() {
+            resolved.date_format = self.date_format.clone();
+        }
+        if self.title_element.is_some() {
+            resolved.title_element = self.title_element.clone();
+        }
+        resolved.ignore_undated |= self.ignore_undated;
+        resolved
+    }
+
+    pub fn apply(&self, syntax: FeedSyntax, value: &mut Value) {
+        if *self == ParseOverrides::default() {
+            return;
+        }
+
+        let overrides = self.resolved();
+        let Some(entries) = syntax.entries(value) else {
+            return;
+        };
+
+        // A feed with a single entry is converted to an object rather than an array
+        if let Value::Object(entry) = entries {
+            *entries = Value::Array(vec![Value::Object(entry.clone())]);
+        }
+
+        if let Value::Array(entries) = entries {
+            for entry in entries.iter_mut() {
+                if let Value::Object(entry) = entry {
+                    overrides.apply_entry(syntax, entry);
+                }
+            }
+
+            if overrides.ignore_undated {
+                entries.retain(|entry| has_date(syntax, entry));
+            }
+        }
+    }
+
+    fn apply_entry(&self, syntax: FeedSyntax, entry: &mut Map<String, Value>) {
+        if let Some(title) = self
+            .title_element
+            .as_ref()
+            .and_then(|element| find_element(entry, element))
+        {
+            entry.insert("title".to_string(), Value::String(title));
+        }
+
+        if self.link_rel.is_some() || self.link_type.is_some() {
+            if let Some(link) = entry.get("link").and_then(|link| self.find_link(link)) {
+                entry.insert("link".to_string(), link);
+            }
+        }
+
+        if let Some(format) = &self.date_format {
+            for field in syntax.date_fields() {
+                let parsed = entry
+                    .get(*field)
+                    .and_then(Value::as_str)
+                    .and_then(|s| parse_custom_date(s, format));
+                if let Some(date) = parsed {
+                    entry.insert(field.to_string(), Value::String(syntax.format_date(date)));
+                }
+            }
+        }
+    }
+
+    fn find_link(&self, link: &Value) -> Option<Value> {
+        let candidates = match link {
+            Value::Array(links) => links.iter().collect(),
+            link => vec![link],
+        };
+
+        candidates.into_iter().find_map(|link| {
+            let link = link.as_object()?;
+            let href = link.get("@href")?.as_str()?;
+            // Atom treats a link without a rel as rel="alternate"
+            let rel = link
+                .get("@rel")
+                .and_then(Value::as_str)
+                .unwrap_or("alternate");
+            let link_type = link.get("@type").and_then(Value::as_str).unwrap_or("");
+
+            let rel_matches = self.link_rel.as_ref().is_none_or(|wanted| wanted == rel);
+            let type_matches = self
+                .link_type
+                .as_ref()
+                .is_none_or(|wanted| wanted == link_type);
+
+            (rel_matches && type_matches).then(|| json!({ "@href": href, "@type": link_type }))
+        })
+    }
+}
+
+// Namespace prefixes are dropped when the XML is converted, and repeated
+// elements are merged into an array, so `itunes:title` following `title`
+// is addressed as `title[1]`.
+fn find_element(entry: &Map<String, Value>, element: &str) -> Option<String> {
+    let (name, index) = match element.split_once('[') {
+        Some((name, index)) => (name, index.trim_end_matches(']').parse::<usize>().ok()?),
+        None => (element, 0),
+    };
+    let name = name.rsplit(':').next().unwrap_or(name);
+
+    let value = match entry.get(name)? {
+        Value::Array(values) => values.get(index)?,
+        value if index == 0 => value,
+        _ => return None,
+    };
+
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Object(map) => map.get("#text")?.as_str().map(String::from),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn has_date(syntax: FeedSyntax, entry: &Value) -> bool {
+    syntax.date_fields().iter().any(|field| {
+        entry
+            .get(*field)
+            .and_then(Value::as_str)
+            .and_then(|s| syntax.parse_date(s))
+            .is_some()
+    })
+}
+
+fn parse_custom_date(s: &str, format: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    DateTime::parse_from_str(s, format)
+        .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(s, format).map(|dt| dt.and_utc()))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, format)
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        })
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::XmlDataSource;
+
+    const RSS: &str = r#"<rss><channel>
+        <item>
+            <title>Episode 1</title>
+            <itunes:title>Rust in Production S01E01</itunes:title>
+            <link>https://example.com/1</link>
+            <pubDate>2024-11-14 06:00</pubDate>
+        </item>
+        <item>
+            <title>Trailer</title>
+            <link>https://example.com/trailer</link>
+        </item>
+    </channel></rss>"#;
+
+    const ATOM: &str = r#"<feed>
+        <entry>
+            <title>Hello</title>
+            <link href="https://example.com/hello.json" rel="alternate" type="application/json"/>
+            <link href="https://example.com/hello" rel="alternate"/>
+            <link href="https://example.com/comments" rel="replies"/>
+            <updated>2024-10-09T18:55:25+00:00</updated>
+        </entry>
+        <entry>
+            <title>World</title>
+            <link href="https://example.com/world"/>
+            <updated>2024-10-10T18:55:25+00:00</updated>
+        </entry>
+    </feed>"#;
+
+    #[test]
+    fn test_rss_without_overrides_fails() {
+        assert!(
+            XmlDataSource::parse_xml_string(RSS, "RIP", "Code", &ParseOverrides::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rss_overrides() {
+        let overrides = ParseOverrides {
+            date_format: Some("%Y-%m-%d %H:%M".to_string()),
+            title_element: Some("itunes:title[1]".to_string()),
+            ignore_undated: true,
+            ..Default::default()
+        };
+
+        let feed = XmlDataSource::parse_xml_string(RSS, "RIP", "Code", &overrides).unwrap();
+        assert_eq!(feed.entries.len(), 1);
+        assert_eq!(feed.entries[0].title, "Rust in Production S01E01");
+        assert_eq!(
+            feed.entries[0].created_date.to_string(),
+            "2024-11-14 06:00:00 UTC"
+        );
+    }
+
+    #[test]
+    fn test_atom_link_rel_override() {
+        let overrides = ParseOverrides {
+            profile: Some(QuirkProfile::AtomAlternateLink),
+            ..Default::default()
+        };
+
+        let feed = XmlDataSource::parse_xml_string(ATOM, "Blog", "Blog", &overrides).unwrap();
+        assert_eq!(feed.entries[0].url, "https://example.com/hello.json");
+        assert_eq!(feed.entries[1].url, "https://example.com/world");
+    }
+
+    #[test]
+    fn test_atom_link_type_override() {
+        let overrides = ParseOverrides {
+            profile: Some(QuirkProfile::AtomAlternateLink),
+            link_type: Some(String::new()),
+            ..Default::default()
+        };
+
+        let feed = XmlDataSource::parse_xml_string(ATOM, "Blog", "Blog", &overrides).unwrap();
+        assert_eq!(feed.entries[0].url, "https://example.com/hello");
+    }
+
+    #[test]
+    fn test_profile_resolution() {
+        let overrides = ParseOverrides {
+            profile: Some(QuirkProfile::AtomAlternateLink),
+            link_rel: Some("related".to_string()),
+            ignore_undated: true,
+            ..Default::default()
+        }
+        .resolved();
+
+        assert_eq!(overrides.link_rel.as_deref(), Some("related"));
+        assert!(overrides.ignore_undated);
+    }
+}
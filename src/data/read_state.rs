@@ -0,0 +1,124 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::ClientError;
+
+/// Marks everything up to `until` (default: now) as read in one feed, one of
+/// the user's own categories (the feeds' own ones without subscriptions), or
+/// (with neither set) every feed.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ReadMarkerInput {
+    pub feed_id: Option<i32>,
+    pub category: Option<String>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ReadEntriesInput {
+    pub urls: Vec<String>,
+}
+
+pub struct ReadStateDataSource {
+    pool: PgPool,
+}
+
+impl ReadStateDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Markers only ever move forward, an older `until` leaves them as they are.
+    pub async fn mark_read_until(
+        &self,
+        user_id: i32,
+        input: ReadMarkerInput,
+    ) -> Result<(), anyhow::Error> {
+        let until = input.until.unwrap_or_else(Utc::now);
+        let query = match (input.feed_id, &input.category) {
+            (Some(_), Some(_)) => {
+                return Err(ClientError::BadRequest(
+                    "A read marker can target a feed or a category, not both".to_string(),
+                )
+                .into())
+            }
+            (Some(feed_id), None) => sqlx::query(
+                "INSERT INTO read_markers (user_id, feed_id, read_until)
+                SELECT $1, id, $3 FROM raw_feeds WHERE id = $2
+                ON CONFLICT (user_id, feed_id) WHERE feed_id IS NOT NULL
+                DO UPDATE SET read_until = GREATEST(read_markers.read_until, EXCLUDED.read_until)",
+            )
+            .bind(user_id)
+            .bind(feed_id)
+            .bind(until),
+            (None, Some(category)) => sqlx::query(
+                "INSERT INTO read_markers (user_id, category, read_until)
+                SELECT $1, $2, $3
+                WHERE EXISTS (SELECT 1 FROM subscriptions WHERE user_id = $1 AND category = $2)
+                OR (
+                    EXISTS (SELECT 1 FROM categories WHERE name = $2)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM subscriptions own
+                        JOIN raw_feeds f ON f.id = own.feed_id
+                        WHERE own.user_id = $1 AND f.deleted_at IS NULL
+                    )
+                )
+                ON CONFLICT (user_id, category) WHERE category IS NOT NULL
+                DO UPDATE SET read_until = GREATEST(read_markers.read_until, EXCLUDED.read_until)",
+            )
+            .bind(user_id)
+            .bind(category)
+            .bind(until),
+            (None, None) => sqlx::query(
+                "INSERT INTO read_markers (user_id, read_until)
+                VALUES ($1, $2)
+                ON CONFLICT (user_id) WHERE feed_id IS NULL AND category IS NULL
+                DO UPDATE SET read_until = GREATEST(read_markers.read_until, EXCLUDED.read_until)",
+            )
+            .bind(user_id)
+            .bind(until),
+        };
+
+        let res = query
+            .execute(&self.pool)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to save read marker")?;
+
+        if res.rows_affected() == 0 {
+            let message = match input.feed_id {
+                Some(feed_id) => format!("Unknown feed: {}", feed_id),
+                None => format!("Unknown category: {}", input.category.unwrap_or_default()),
+            };
+            return Err(ClientError::NotFound(message).into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns how many of the given entries were found and marked.
+    pub async fn mark_entries_read(
+        &self,
+        user_id: i32,
+        input: ReadEntriesInput,
+    ) -> Result<u64, anyhow::Error> {
+        let res = sqlx::query(
+            "INSERT INTO entry_reads (user_id, entry_id)
+            SELECT $1, id FROM cached_entries WHERE url = ANY($2)
+            ON CONFLICT (user_id, entry_id) DO NOTHING;",
+        )
+        .bind(user_id)
+        .bind(&input.urls)
+        .execute(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to mark entries read")?;
+
+        Ok(res.rows_affected())
+    }
+}
@@ -0,0 +1,386 @@
+use std::future::Future;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, PgConnection, PgPool};
+
+use crate::error::ClientError;
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(tag = "keep", rename_all = "snake_case")]
+pub enum RetentionPolicy {
+    All,
+    LastEntries { count: i32 },
+    NewerThan { days: i32 },
+}
+
+/// Targets a single feed, a whole category, or (with neither set) every feed.
+impl RetentionPolicy {
+    fn validate(&self) -> Result<(), ClientError> {
+        match self {
+            RetentionPolicy::LastEntries { count } if *count < 0 => Err(ClientError::BadRequest(
+                "A retention rule can't keep a negative number of entries".to_string(),
+            )),
+            RetentionPolicy::NewerThan { days } if *days < 0 => Err(ClientError::BadRequest(
+                "A retention rule can't keep entries for a negative number of days".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RetentionRuleInput {
+    pub feed_id: Option<i32>,
+    pub category: Option<String>,
+    pub policy: RetentionPolicy,
+}
+
+#[derive(Deserialize, Serialize, Debug, FromRow)]
+pub struct RetentionRule {
+    pub id: i32,
+    pub feed_id: Option<i32>,
+    pub feed: Option<String>,
+    pub category: Option<String>,
+    pub policy: Json<RetentionPolicy>,
+}
+
+#[derive(Debug, FromRow)]
+struct DBFeedRetention {
+    cached_feed_id: i32,
+    name: String,
+    feed_policy: Option<Json<RetentionPolicy>>,
+    category_policy: Option<Json<RetentionPolicy>>,
+    global_policy: Option<Json<RetentionPolicy>>,
+}
+
+impl DBFeedRetention {
+    /// The most specific rule wins: feed, then category, then global.
+    fn policy(&self) -> Option<RetentionPolicy> {
+        [
+            &self.feed_policy,
+            &self.category_policy,
+            &self.global_policy,
+        ]
+        .into_iter()
+        .find_map(|policy| policy.as_ref().map(|Json(policy)| *policy))
+    }
+}
+
+#[derive(Default, Deserialize, Serialize, Debug)]
+pub struct PruneReport {
+    pub feeds_pruned: usize,
+    pub entries_removed: u64,
+}
+
+pub struct RetentionDataSource {
+    pool: PgPool,
+}
+
+impl RetentionDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_retention_rules(&self) -> Result<Vec<RetentionRule>, anyhow::Error> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to acquire connection")?;
+
+        Self::fetch_retention_rules(&mut conn).await
+    }
+
+    /// Like `get_retention_rules`, inside a change's transaction.
+    pub async fn fetch_retention_rules(
+        conn: &mut PgConnection,
+    ) -> Result<Vec<RetentionRule>, anyhow::Error> {
+        let res = sqlx::query_as::<_, RetentionRule>(
+            "SELECT rp.id, rp.feed_id, rf.name AS feed, c.name AS category, rp.policy
+            FROM retention_policies rp
+            LEFT JOIN raw_feeds rf ON rf.id = rp.feed_id
+            LEFT JOIN categories c ON c.id = rp.category_id
+            ORDER BY rp.id;",
+        )
+        .fetch_all(conn)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get retention rules from db")?;
+
+        Ok(res)
+    }
+
+    pub async fn set_retention_rule(
+        conn: &mut PgConnection,
+        input: RetentionRuleInput,
+    ) -> Result<(), anyhow::Error> {
+        input.policy.validate()?;
+        let policy = Json(input.policy);
+        let query = match (input.feed_id, &input.category) {
+            (Some(_), Some(_)) => {
+                return Err(ClientError::BadRequest(
+                    "A retention rule can target a feed or a category, not both".to_string(),
+                )
+                .into())
+            }
+            (Some(feed_id), None) => sqlx::query(
+                "INSERT INTO retention_policies (feed_id, policy)
+                SELECT id, $2 FROM raw_feeds WHERE id = $1
+                ON CONFLICT (feed_id) DO UPDATE SET policy = EXCLUDED.policy",
+            )
+            .bind(feed_id)
+            .bind(policy),
+            (None, Some(category)) => sqlx::query(
+                "INSERT INTO retention_policies (category_id, policy)
+                SELECT id, $2 FROM categories WHERE name = $1
+                ON CONFLICT (category_id) DO UPDATE SET policy = EXCLUDED.policy",
+            )
+            .bind(category)
+            .bind(policy),
+            (None, None) => sqlx::query(
+                "INSERT INTO retention_policies (policy)
+                VALUES ($1)
+                ON CONFLICT ((TRUE)) WHERE feed_id IS NULL AND category_id IS NULL
+                DO UPDATE SET policy = EXCLUDED.policy",
+            )
+            .bind(policy),
+        };
+
+        let res = query
+            .execute(conn)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context("Failed to save retention rule")?;
+
+        if res.rows_affected() == 0 {
+            let message = match input.feed_id {
+                Some(feed_id) => format!("Unknown feed: {}", feed_id),
+                None => format!("Unknown category: {}", input.category.unwrap_or_default()),
+            };
+            return Err(ClientError::NotFound(message).into());
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete_retention_rule(
+        conn: &mut PgConnection,
+        id: i32,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query("DELETE FROM retention_policies WHERE id = $1;")
+            .bind(id)
+            .execute(conn)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!("Error while deleting retention rule: {}", id))?;
+
+        Ok(())
+    }
+
+    /// Applies the most specific rule for every cached feed: feed, then category, then global.
+    /// Starred entries are never removed and don't count towards `LastEntries`.
+    pub async fn prune_entries(&self, batch_size: i64) -> Result<PruneReport, anyhow::Error> {
+        let batch_size = batch_size.max(1);
+        let feeds = sqlx::query_as::<_, DBFeedRetention>(
+            "SELECT
+                cached.id AS cached_feed_id,
+                cached.name,
+                fp.policy AS feed_policy,
+                cp.policy AS category_policy,
+                gp.policy AS global_policy
+            FROM cached_feeds cached
+            LEFT JOIN raw_feeds rf ON rf.name = cached.name
+            LEFT JOIN retention_policies fp ON fp.feed_id = rf.id
+            LEFT JOIN retention_policies cp ON cp.category_id = cached.category_id
+            LEFT JOIN retention_policies gp
+                ON gp.feed_id IS NULL AND gp.category_id IS NULL;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to resolve retention rules")?;
+
+        let mut report = PruneReport::default();
+        for feed in feeds {
+            // Rules saved before negative values were rejected are skipped
+            // rather than pruning everything
+            let Some(policy) = feed.policy().filter(|policy| policy.validate().is_ok()) else {
+                continue;
+            };
+
+            let removed = prune_in_batches(batch_size, || {
+                self.prune_batch(feed.cached_feed_id, policy, batch_size)
+            })
+            .await
+            .context(format!("Failed to prune entries for feed: {}", feed.name))?;
+
+            if removed > 0 {
+                report.feeds_pruned += 1;
+                report.entries_removed += removed;
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn prune_batch(
+        &self,
+        cached_feed_id: i32,
+        policy: RetentionPolicy,
+        batch_size: i64,
+    ) -> Result<u64, anyhow::Error> {
+        let query = match policy {
+            RetentionPolicy::All => return Ok(0),
+            RetentionPolicy::LastEntries { count } => sqlx::query(
+                "DELETE FROM cached_entries
+                WHERE id IN (
+                    SELECT id FROM cached_entries e
+                    WHERE feed_id = $1
+                    AND NOT EXISTS (SELECT 1 FROM starred_entries s WHERE s.entry_id = e.id)
+                    ORDER BY created_date DESC, id DESC
+                    OFFSET $2
+                    LIMIT $3
+                );",
+            )
+            .bind(cached_feed_id)
+            .bind(count as i64),
+            RetentionPolicy::NewerThan { days } => sqlx::query(
+                "DELETE FROM cached_entries
+                WHERE id IN (
+                    SELECT id FROM cached_entries e
+                    WHERE feed_id = $1
+                    AND created_date < NOW() - make_interval(days => $2)
+                    AND NOT EXISTS (SELECT 1 FROM starred_entries s WHERE s.entry_id = e.id)
+                    LIMIT $3
+                );",
+            )
+            .bind(cached_feed_id)
+            .bind(days),
+        };
+
+        let res = query
+            .bind(batch_size)
+            .execute(&self.pool)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })?;
+
+        Ok(res.rows_affected())
+    }
+}
+
+/// Runs `prune_batch` until a batch removes fewer than `batch_size` entries,
+/// so no single delete holds locks on a large feed for long.
+async fn prune_in_batches<F, Fut>(batch_size: i64, mut prune_batch: F) -> Result<u64, anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<u64, anyhow::Error>>,
+{
+    let batch_size = batch_size as u64;
+    let mut removed = 0;
+    loop {
+        let batch = prune_batch().await?;
+        removed += batch;
+        if batch < batch_size {
+            return Ok(removed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn retention(
+        feed: Option<RetentionPolicy>,
+        category: Option<RetentionPolicy>,
+        global: Option<RetentionPolicy>,
+    ) -> DBFeedRetention {
+        DBFeedRetention {
+            cached_feed_id: 1,
+            name: "Feed".to_string(),
+            feed_policy: feed.map(Json),
+            category_policy: category.map(Json),
+            global_policy: global.map(Json),
+        }
+    }
+
+    #[test]
+    fn test_policy_precedence() {
+        let feed = RetentionPolicy::LastEntries { count: 10 };
+        let category = RetentionPolicy::NewerThan { days: 30 };
+        let global = RetentionPolicy::All;
+
+        assert_eq!(
+            retention(Some(feed), Some(category), Some(global)).policy(),
+            Some(feed)
+        );
+        assert_eq!(
+            retention(None, Some(category), Some(global)).policy(),
+            Some(category)
+        );
+        assert_eq!(retention(None, None, Some(global)).policy(), Some(global));
+        assert_eq!(retention(Some(feed), None, None).policy(), Some(feed));
+        assert_eq!(retention(None, None, None).policy(), None);
+    }
+
+    #[test]
+    fn test_negative_policies_rejected() {
+        assert!(RetentionPolicy::LastEntries { count: -1 }
+            .validate()
+            .is_err());
+        assert!(RetentionPolicy::NewerThan { days: -1 }.validate().is_err());
+        assert!(RetentionPolicy::LastEntries { count: 0 }.validate().is_ok());
+        assert!(RetentionPolicy::NewerThan { days: 30 }.validate().is_ok());
+        assert!(RetentionPolicy::All.validate().is_ok());
+    }
+
+    async fn prune(batch_size: i64, entries: u64) -> (u64, usize) {
+        let remaining = Cell::new(entries);
+        let calls = Cell::new(0);
+        let removed = prune_in_batches(batch_size, || {
+            calls.set(calls.get() + 1);
+            let batch = remaining.get().min(batch_size as u64);
+            remaining.set(remaining.get() - batch);
+            async move { Ok(batch) }
+        })
+        .await
+        .unwrap();
+        (removed, calls.get())
+    }
+
+    #[tokio::test]
+    async fn test_prune_in_batches() {
+        assert_eq!(prune(500, 1200).await, (1200, 3));
+        assert_eq!(prune(500, 1000).await, (1000, 3));
+        assert_eq!(prune(500, 0).await, (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_prune_in_batches_stops_on_error() {
+        let calls = Cell::new(0);
+        let res = prune_in_batches(10, || {
+            calls.set(calls.get() + 1);
+            async { Err(anyhow::Error::msg("Database error")) }
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+}
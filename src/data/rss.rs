@@ -2,6 +2,8 @@ use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::{from_value, Value};
 
+use super::xml::optional_text;
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct RSSLink {
     #[serde(rename = "@href")]
@@ -18,6 +20,11 @@ pub struct RSSItem {
     pub pub_date: DateTime<Utc>,
     #[serde(deserialize_with = "title")]
     pub title: String,
+    #[serde(deserialize_with = "optional_text", default)]
+    pub description: Option<String>,
+    /// `content:encoded`, with the namespace prefix already stripped
+    #[serde(deserialize_with = "optional_text", default)]
+    pub encoded: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -68,14 +75,11 @@ where
                 return Ok(link);
             }
 
-            for element in v {
-                match element {
-                    LinkElement::Simple(s) => return Ok(s),
-                    LinkElement::Complex(link) => return Ok(link.href),
-                }
+            match v.into_iter().next() {
+                Some(LinkElement::Simple(s)) => Ok(s),
+                Some(LinkElement::Complex(link)) => Ok(link.href),
+                None => Err(serde::de::Error::custom("No valid link found in array")),
             }
-
-            Err(serde::de::Error::custom("No valid link found in array"))
         }
         LinkMultiType::Single(element) => match element {
             LinkElement::Complex(link) => Ok(link.href),
@@ -108,6 +112,11 @@ where
 {
     let s = String::deserialize(deserializer)?;
 
+    parse_rss_date(&s)
+        .ok_or_else(|| de::Error::custom(format!("Failed to parse RSS date: {}", &s)))
+}
+
+pub fn parse_rss_date(s: &str) -> Option<DateTime<Utc>> {
     // Define multiple date formats
     let formats = [
         "%a, %d %b %Y %H:%M:%S %z",  // Example: Wed, 11 Sep 2024 00:00:00 -0400
@@ -115,18 +124,12 @@ where
         "%a, %d %b %Y %H:%M:%S UTC", // Example: Tue, 26 Nov 2024 17:21:05 UTC
     ];
 
-    for format in &formats {
-        if let Ok(dt) = NaiveDateTime::parse_from_str(&s, format) {
-            return Ok(dt.and_utc());
-        }
-    }
-
-    Err(de::Error::custom(&format!(
-        "Failed to parse RSS date: {}",
-        &s
-    )))
+    formats
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .map(|dt| dt.and_utc())
 }
 
 pub fn rss_to_json(value: Value) -> Result<RSSObject, anyhow::Error> {
-    from_value(value).map_err(|e| anyhow::Error::from(e))
+    from_value(value).map_err(anyhow::Error::from)
 }
@@ -0,0 +1,132 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use chrono::Utc;
+use sqlx::{postgres::PgPoolOptions, Executor, PgPool};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::Mutex,
+    time::{self, Duration},
+};
+
+/// Advisory locks are shared by the whole database rather than a schema, so
+/// tests that take the scheduler lock hold this while they do.
+pub static SCHEDULER_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// A migrated, empty schema of its own on the database at `DATABASE_URL`, so
+/// tests that need Postgres don't step on each other or on real data.
+pub struct ScratchDatabase {
+    pub pool: PgPool,
+    schema: String,
+}
+
+impl ScratchDatabase {
+    pub async fn new(schema: &str) -> Self {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let admin = PgPool::connect(&url).await.unwrap();
+        admin
+            .execute(
+                format!("DROP SCHEMA IF EXISTS {schema} CASCADE; CREATE SCHEMA {schema};").as_str(),
+            )
+            .await
+            .unwrap();
+
+        let search_path = format!("SET search_path TO {schema}");
+        let pool = PgPoolOptions::new()
+            .after_connect(move |conn, _| {
+                let search_path = search_path.clone();
+                Box::pin(async move {
+                    conn.execute(search_path.as_str()).await?;
+                    Ok(())
+                })
+            })
+            .connect(&url)
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        Self {
+            pool,
+            schema: schema.to_string(),
+        }
+    }
+
+    pub async fn drop(self) {
+        self.pool.close().await;
+        let admin = PgPool::connect(&std::env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        admin
+            .execute(format!("DROP SCHEMA {} CASCADE", self.schema).as_str())
+            .await
+            .unwrap();
+    }
+}
+
+/// Serves a two-entry RSS feed on loopback and counts the requests it gets,
+/// so refresh tests can tell how often a feed was actually fetched.
+pub struct FeedServer {
+    pub url: String,
+    hits: Arc<AtomicUsize>,
+}
+
+impl FeedServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/rss", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+
+                    let body = format!(
+                        "<rss><channel>\
+                        <item><title>First</title><link>https://example.com/first</link>\
+                        <pubDate>{0}</pubDate></item>\
+                        <item><title>Second</title><link>https://example.com/second</link>\
+                        <pubDate>{0}</pubDate></item>\
+                        </channel></rss>",
+                        Utc::now().to_rfc2822()
+                    );
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/rss+xml\r\n\
+                        Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        Self { url, hits }
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+    /// Waits for background fetches to reach the server, failing after a while.
+    pub async fn wait_for_hits(&self, hits: usize) {
+        for _ in 0..100 {
+            if self.hits() >= hits {
+                return;
+            }
+            time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Expected {} requests, got {}", hits, self.hits());
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+/// `q` uses web search syntax: `"quoted phrases"`, `or` and `-excluded` words.
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    pub q: String,
+    pub category: Option<String>,
+    pub feed: Option<String>,
+    /// Inclusive date range on the entry's publish date
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, FromRow)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub created_date: DateTime<Utc>,
+    pub feed: String,
+    pub category: String,
+    pub rank: f32,
+    /// Matching fragments as escaped HTML, with the matched words wrapped in `<mark>`
+    pub snippet: String,
+}
+
+/// Marks the matched words in `ts_headline` output. Neither can occur in
+/// entry text, they are stripped from it before the headline is built.
+const MATCH_START: char = '\u{2}';
+const MATCH_STOP: char = '\u{3}';
+
+pub struct SearchDataSource {
+    pool: PgPool,
+}
+
+impl SearchDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>, anyhow::Error> {
+        let res = sqlx::query_as::<_, SearchResult>(
+            r#"SELECT
+                e.title,
+                e.url,
+                e.created_date,
+                cached.name AS feed,
+                c.name AS category,
+                ts_rank_cd(e.search, query) AS rank,
+                ts_headline(
+                    'english',
+                    translate(
+                        e.title || ' ' || coalesce(regexp_replace(e.content, '<[^>]*(>|$)', ' ', 'g'), ''),
+                        chr(2) || chr(3),
+                        ''
+                    ),
+                    query,
+                    'StartSel=' || chr(2) || ', StopSel=' || chr(3) || ', MaxWords=30, MinWords=10, MaxFragments=2'
+                ) AS snippet
+            FROM cached_entries e
+            JOIN cached_feeds cached ON cached.id = e.feed_id
+            JOIN raw_feeds raw ON raw.name = cached.name
+            JOIN categories c ON c.id = cached.category_id,
+            websearch_to_tsquery('english', $1) query
+            WHERE e.search @@ query
+            AND feed_enabled(raw)
+            AND ($2::varchar IS NULL OR c.name = $2)
+            AND ($3::varchar IS NULL OR cached.name = $3)
+            AND ($4::date IS NULL OR e.created_date >= $4)
+            AND ($5::date IS NULL OR e.created_date < $5 + 1)
+            ORDER BY rank DESC, e.created_date DESC
+            LIMIT $6
+            OFFSET $7;"#,
+        )
+        .bind(&query.q)
+        .bind(&query.category)
+        .bind(&query.feed)
+        .bind(query.from)
+        .bind(query.to)
+        .bind(query.limit.unwrap_or(20).clamp(1, 100))
+        .bind(query.offset.unwrap_or(0).max(0))
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to search cached entries")?;
+
+        Ok(res
+            .into_iter()
+            .map(|result| SearchResult {
+                snippet: snippet_html(&result.snippet),
+                ..result
+            })
+            .collect())
+    }
+}
+
+/// Escapes a headline for use as HTML, then turns the match markers into `<mark>`.
+fn snippet_html(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+    for c in headline.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_STOP => html.push_str("</mark>"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snippet_marks_matches() {
+        assert_eq!(
+            snippet_html("Rust \u{2}release\u{3} notes"),
+            "Rust <mark>release</mark> notes"
+        );
+    }
+
+    #[test]
+    fn test_snippet_escapes_markup() {
+        assert_eq!(
+            snippet_html("<img src=x onerror=alert(1) \u{2}rust\u{3} & \"more\""),
+            "&lt;img src=x onerror=alert(1) <mark>rust</mark> &amp; &quot;more&quot;"
+        );
+        assert_eq!(
+            snippet_html("</mark><script>"),
+            "&lt;/mark&gt;&lt;script&gt;"
+        );
+    }
+}
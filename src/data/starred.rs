@@ -0,0 +1,113 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+use crate::error::ClientError;
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct StarInput {
+    pub url: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, FromRow)]
+pub struct StarredEntry {
+    pub title: String,
+    pub url: String,
+    pub created_date: DateTime<Utc>,
+    pub feed: String,
+    pub category: String,
+    pub starred_at: DateTime<Utc>,
+}
+
+pub struct StarredDataSource {
+    pool: PgPool,
+}
+
+impl StarredDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_starred(&self, user_id: i32) -> Result<Vec<StarredEntry>, anyhow::Error> {
+        let res = sqlx::query_as::<_, StarredEntry>(
+            "SELECT e.title, e.url, e.created_date, cached.name AS feed, c.name AS category,
+                s.starred_at
+            FROM starred_entries s
+            JOIN cached_entries e ON e.id = s.entry_id
+            JOIN cached_feeds cached ON cached.id = e.feed_id
+            JOIN categories c ON c.id = cached.category_id
+            WHERE s.user_id = $1
+            ORDER BY s.starred_at DESC;",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get starred entries from db")?;
+
+        Ok(res)
+    }
+
+    pub async fn star(&self, user_id: i32, input: StarInput) -> Result<(), anyhow::Error> {
+        let res = sqlx::query(
+            "INSERT INTO starred_entries (user_id, entry_id)
+            SELECT $1, id FROM cached_entries WHERE url = $2
+            ON CONFLICT (user_id, entry_id) DO NOTHING;",
+        )
+        .bind(user_id)
+        .bind(&input.url)
+        .execute(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while starring entry: {}", input.url))?;
+
+        if res.rows_affected() == 0 && !self.is_starred(user_id, &input.url).await? {
+            return Err(ClientError::NotFound(format!("Unknown entry: {}", input.url)).into());
+        }
+
+        Ok(())
+    }
+
+    pub async fn unstar(&self, user_id: i32, input: StarInput) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            "DELETE FROM starred_entries
+            WHERE user_id = $1
+            AND entry_id IN (SELECT id FROM cached_entries WHERE url = $2);",
+        )
+        .bind(user_id)
+        .bind(&input.url)
+        .execute(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while unstarring entry: {}", input.url))?;
+
+        Ok(())
+    }
+
+    async fn is_starred(&self, user_id: i32, url: &str) -> Result<bool, anyhow::Error> {
+        let res = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (
+                SELECT 1 FROM starred_entries s
+                JOIN cached_entries e ON e.id = s.entry_id
+                WHERE s.user_id = $1 AND e.url = $2
+            );",
+        )
+        .bind(user_id)
+        .bind(url)
+        .fetch_one(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to check starred entry: {}", url))?;
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+use super::redact_url;
+use crate::error::ClientError;
+
+/// Subscribes to the catalog feed with this id or URL. The admin can subscribe
+/// to a URL that isn't in the catalog yet, which adds it under `name`.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SubscriptionInput {
+    /// Catalog URLs are redacted, so feeds whose URL has a query are
+    /// subscribed to by id
+    pub feed_id: Option<i32>,
+    pub url: Option<String>,
+    pub name: Option<String>,
+    pub category: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SubscriptionUpdateInput {
+    pub category: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, FromRow)]
+pub struct Subscription {
+    pub id: i32,
+    pub feed_id: i32,
+    pub name: String,
+    pub url: String,
+    pub category: String,
+    pub created_date: DateTime<Utc>,
+}
+
+impl Subscription {
+    fn redacted(self) -> Self {
+        Self {
+            url: redact_url(&self.url),
+            ..self
+        }
+    }
+}
+
+/// A feed of the shared catalog, without its fetch configuration.
+#[derive(Deserialize, Serialize, Debug, FromRow)]
+pub struct CatalogFeed {
+    pub id: i32,
+    pub name: String,
+    pub url: String,
+    pub category: String,
+    pub subscribers: i64,
+}
+
+pub struct SubscriptionDataSource {
+    pool: PgPool,
+}
+
+impl SubscriptionDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_catalog(&self) -> Result<Vec<CatalogFeed>, anyhow::Error> {
+        let res = sqlx::query_as::<_, CatalogFeed>(
+            "SELECT raw.id, raw.name, raw.url, c.name AS category, count(sub.id) AS subscribers
+            FROM raw_feeds raw
+            JOIN categories c ON c.id = raw.category_id
+            LEFT JOIN subscriptions sub ON sub.feed_id = raw.id
+            WHERE feed_enabled(raw)
+            GROUP BY raw.id, c.name
+            ORDER BY raw.name;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get feed catalog from db")?;
+
+        Ok(res
+            .into_iter()
+            .map(|feed| CatalogFeed {
+                url: redact_url(&feed.url),
+                ..feed
+            })
+            .collect())
+    }
+
+    pub async fn get_subscriptions(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<Subscription>, anyhow::Error> {
+        let res = sqlx::query_as::<_, Subscription>(
+            "SELECT sub.id, sub.feed_id, raw.name, raw.url, sub.category, sub.created_date
+            FROM subscriptions sub
+            JOIN raw_feeds raw ON raw.id = sub.feed_id
+            WHERE sub.user_id = $1 AND raw.deleted_at IS NULL
+            ORDER BY sub.category, raw.name;",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to get subscriptions from db")?;
+
+        Ok(res.into_iter().map(Subscription::redacted).collect())
+    }
+
+    /// Subscribing again to the same feed only moves it to the new category.
+    pub async fn subscribe(
+        &self,
+        user_id: i32,
+        feed_id: i32,
+        category: &str,
+    ) -> Result<Subscription, anyhow::Error> {
+        let res = sqlx::query_as::<_, Subscription>(
+            "WITH sub AS (
+                INSERT INTO subscriptions (user_id, feed_id, category)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (user_id, feed_id) DO UPDATE SET category = EXCLUDED.category
+                RETURNING id, feed_id, category, created_date
+            )
+            SELECT sub.id, sub.feed_id, raw.name, raw.url, sub.category, sub.created_date
+            FROM sub
+            JOIN raw_feeds raw ON raw.id = sub.feed_id;",
+        )
+        .bind(user_id)
+        .bind(feed_id)
+        .bind(category)
+        .fetch_one(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Error while subscribing to feed: {}", feed_id))?;
+
+        Ok(res.redacted())
+    }
+
+    pub async fn update_subscription(
+        &self,
+        user_id: i32,
+        id: i32,
+        input: SubscriptionUpdateInput,
+    ) -> Result<(), anyhow::Error> {
+        let res =
+            sqlx::query("UPDATE subscriptions SET category = $3 WHERE id = $2 AND user_id = $1;")
+                .bind(user_id)
+                .bind(id)
+                .bind(&input.category)
+                .execute(&self.pool)
+                .await
+                .inspect_err(|e| {
+                    eprintln!("Database error: {:?}", e);
+                })
+                .context(format!("Error while updating subscription: {}", id))?;
+
+        if res.rows_affected() == 0 {
+            return Err(ClientError::NotFound(format!("Unknown subscription: {}", id)).into());
+        }
+
+        Ok(())
+    }
+
+    /// The catalog feed stays, other users may still be subscribed to it.
+    pub async fn unsubscribe(&self, user_id: i32, id: i32) -> Result<(), anyhow::Error> {
+        let res = sqlx::query("DELETE FROM subscriptions WHERE id = $2 AND user_id = $1;")
+            .bind(user_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .inspect_err(|e| {
+                eprintln!("Database error: {:?}", e);
+            })
+            .context(format!("Error while deleting subscription: {}", id))?;
+
+        if res.rows_affected() == 0 {
+            return Err(ClientError::NotFound(format!("Unknown subscription: {}", id)).into());
+        }
+
+        Ok(())
+    }
+}
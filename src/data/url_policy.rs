@@ -0,0 +1,399 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use reqwest::Url;
+use tokio::net::lookup_host;
+
+use super::FetchError;
+
+#[derive(Clone, Debug, PartialEq)]
+enum AllowEntry {
+    Host(String),
+    Network(IpAddr, u8),
+}
+
+impl AllowEntry {
+    fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return None;
+        }
+
+        if let Some((addr, prefix)) = entry.split_once('/') {
+            let addr = addr.parse::<IpAddr>().ok()?;
+            let prefix = prefix.parse::<u8>().ok()?;
+            let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+            return (prefix <= max_prefix).then_some(AllowEntry::Network(addr, prefix));
+        }
+
+        match entry.parse::<IpAddr>() {
+            Ok(addr) => {
+                let prefix = if addr.is_ipv4() { 32 } else { 128 };
+                Some(AllowEntry::Network(addr, prefix))
+            }
+            Err(_) => Some(AllowEntry::Host(entry.to_ascii_lowercase())),
+        }
+    }
+
+    fn contains_ip(&self, ip: IpAddr) -> bool {
+        match (self, ip) {
+            (AllowEntry::Network(IpAddr::V4(network), prefix), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(ip) & mask
+            }
+            (AllowEntry::Network(IpAddr::V6(network), prefix), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Decides which URLs the service is willing to fetch on a user's behalf.
+///
+/// Everything that resolves to loopback, link-local, private or cloud metadata
+/// addresses is refused unless the host or address is explicitly allow-listed.
+#[derive(Clone, Debug, Default)]
+pub struct UrlPolicy {
+    allow_list: Vec<AllowEntry>,
+}
+
+impl UrlPolicy {
+    /// Builds a policy from a comma separated list of hostnames, IPs and CIDR ranges.
+    pub fn new(allow_list: &str) -> Self {
+        Self {
+            allow_list: allow_list
+                .split(',')
+                .filter_map(AllowEntry::parse)
+                .collect(),
+        }
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.allow_list
+            .iter()
+            .any(|entry| matches!(entry, AllowEntry::Host(allowed) if *allowed == host))
+    }
+
+    fn ip_allowed(&self, ip: IpAddr) -> bool {
+        self.allow_list.iter().any(|entry| entry.contains_ip(ip))
+    }
+
+    pub fn parse(&self, url: &str) -> Result<Url, FetchError> {
+        let url = Url::parse(url).map_err(|e| FetchError::InvalidUrl {
+            reason: e.to_string(),
+        })?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(FetchError::InvalidUrl {
+                reason: format!("Unsupported URL scheme: {}", url.scheme()),
+            });
+        }
+
+        if url.host_str().is_none() {
+            return Err(FetchError::InvalidUrl {
+                reason: "URL has no host".to_string(),
+            });
+        }
+
+        Ok(url)
+    }
+
+    /// Resolves the URL's host and returns the addresses it is safe to connect to.
+    pub async fn resolve(&self, url: &Url) -> Result<Vec<SocketAddr>, FetchError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| FetchError::InvalidUrl {
+                reason: "URL has no host".to_string(),
+            })?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        let addrs: Vec<SocketAddr> = lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| FetchError::InvalidUrl {
+                reason: format!("Failed to resolve host '{}': {}", host, e),
+            })?
+            .collect();
+
+        if addrs.is_empty() {
+            return Err(FetchError::InvalidUrl {
+                reason: format!("Host '{}' did not resolve to any address", host),
+            });
+        }
+
+        if self.host_allowed(&host) {
+            return Ok(addrs);
+        }
+
+        for addr in &addrs {
+            if self.ip_allowed(addr.ip()) {
+                continue;
+            }
+            if let Some(class) = blocked_ip_class(addr.ip()) {
+                return Err(FetchError::BlockedAddress {
+                    host: host.clone(),
+                    ip: addr.ip(),
+                    class,
+                });
+            }
+        }
+
+        Ok(addrs)
+    }
+
+    pub async fn check(&self, url: &str) -> Result<Url, FetchError> {
+        let url = self.parse(url)?;
+        self.resolve(&url).await?;
+        Ok(url)
+    }
+}
+
+const METADATA_IPS: [IpAddr; 3] = [
+    IpAddr::V4(Ipv4Addr::new(169, 254, 169, 254)),
+    IpAddr::V4(Ipv4Addr::new(100, 100, 100, 200)),
+    IpAddr::V6(Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254)),
+];
+
+/// Returns the name of the blocked range an address falls in, if any.
+fn blocked_ip_class(ip: IpAddr) -> Option<&'static str> {
+    if METADATA_IPS.contains(&ip) {
+        return Some("metadata");
+    }
+
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            if ip.is_loopback() {
+                Some("loopback")
+            } else if ip.is_unspecified() || octets[0] == 0 {
+                Some("unspecified")
+            } else if ip.is_link_local() {
+                Some("link-local")
+            } else if ip.is_private() {
+                Some("private")
+            } else if octets[0] == 100 && (64..128).contains(&octets[1]) {
+                Some("shared")
+            } else if octets[0] == 198 && (18..20).contains(&octets[1]) {
+                Some("benchmarking")
+            } else if octets[..3] == [192, 0, 0] {
+                Some("protocol-assignment")
+            } else if ip.is_broadcast() || ip.is_multicast() {
+                Some("multicast")
+            } else if octets[0] >= 240 {
+                Some("reserved")
+            } else {
+                None
+            }
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return blocked_ip_class(IpAddr::V4(mapped));
+            }
+
+            // Addresses that embed an IPv4 address are translated or tunnelled
+            // to it by infrastructure we cannot see, so they are never fetched.
+            let segments = ip.segments();
+            let first_segment = segments[0];
+            if ip.is_loopback() {
+                Some("loopback")
+            } else if ip.is_unspecified() {
+                Some("unspecified")
+            } else if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+                || segments[..3] == [0x64, 0xff9b, 1]
+            {
+                Some("nat64")
+            } else if segments[..6] == [0; 6] {
+                Some("ipv4-compatible")
+            } else if first_segment == 0x2002 {
+                Some("6to4")
+            } else if segments[..2] == [0x2001, 0] {
+                Some("teredo")
+            } else if first_segment & 0xffc0 == 0xfe80 {
+                Some("link-local")
+            } else if first_segment & 0xfe00 == 0xfc00 {
+                Some("private")
+            } else if ip.is_multicast() {
+                Some("multicast")
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocked(url: &str, policy: &UrlPolicy) -> Option<&'static str> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        match runtime.block_on(policy.check(url)) {
+            Err(FetchError::BlockedAddress { class, .. }) => Some(class),
+            Err(e) => panic!("Unexpected error for {}: {}", url, e),
+            Ok(_) => None,
+        }
+    }
+
+    #[test]
+    fn test_rejects_non_http_schemes() {
+        let policy = UrlPolicy::default();
+        for url in [
+            "file:///etc/passwd",
+            "ftp://example.com/feed",
+            "gopher://x/",
+        ] {
+            assert!(matches!(
+                policy.parse(url),
+                Err(FetchError::InvalidUrl { .. })
+            ));
+        }
+        assert!(policy.parse("https://example.com/feed.xml").is_ok());
+    }
+
+    #[test]
+    fn test_blocks_loopback() {
+        let policy = UrlPolicy::default();
+        assert_eq!(blocked("http://127.0.0.1/feed", &policy), Some("loopback"));
+        assert_eq!(
+            blocked("http://127.8.9.10:8080/", &policy),
+            Some("loopback")
+        );
+        assert_eq!(blocked("http://[::1]/feed", &policy), Some("loopback"));
+    }
+
+    #[test]
+    fn test_blocks_private() {
+        let policy = UrlPolicy::default();
+        assert_eq!(blocked("http://10.0.0.5/", &policy), Some("private"));
+        assert_eq!(blocked("http://172.16.3.4/", &policy), Some("private"));
+        assert_eq!(blocked("http://192.168.1.1/", &policy), Some("private"));
+        assert_eq!(blocked("http://[fd12:3456::1]/", &policy), Some("private"));
+    }
+
+    #[test]
+    fn test_blocks_link_local() {
+        let policy = UrlPolicy::default();
+        assert_eq!(blocked("http://169.254.10.1/", &policy), Some("link-local"));
+        assert_eq!(blocked("http://[fe80::1]/", &policy), Some("link-local"));
+    }
+
+    #[test]
+    fn test_blocks_metadata() {
+        let policy = UrlPolicy::default();
+        assert_eq!(
+            blocked("http://169.254.169.254/latest/meta-data/", &policy),
+            Some("metadata")
+        );
+        assert_eq!(
+            blocked("http://100.100.100.200/", &policy),
+            Some("metadata")
+        );
+        assert_eq!(
+            blocked("http://[fd00:ec2::254]/", &policy),
+            Some("metadata")
+        );
+    }
+
+    #[test]
+    fn test_blocks_other_reserved_ranges() {
+        let policy = UrlPolicy::default();
+        assert_eq!(blocked("http://0.0.0.0/", &policy), Some("unspecified"));
+        assert_eq!(blocked("http://100.64.0.1/", &policy), Some("shared"));
+        assert_eq!(
+            blocked("http://[::ffff:127.0.0.1]/", &policy),
+            Some("loopback")
+        );
+    }
+
+    #[test]
+    fn test_blocks_benchmarking() {
+        let policy = UrlPolicy::default();
+        assert_eq!(blocked("http://198.18.0.1/", &policy), Some("benchmarking"));
+        assert_eq!(
+            blocked("http://198.19.255.254/", &policy),
+            Some("benchmarking")
+        );
+        assert_eq!(blocked("http://198.20.0.1/", &policy), None);
+    }
+
+    #[test]
+    fn test_blocks_protocol_assignments() {
+        let policy = UrlPolicy::default();
+        assert_eq!(
+            blocked("http://192.0.0.170/", &policy),
+            Some("protocol-assignment")
+        );
+        assert_eq!(blocked("http://192.0.1.1/", &policy), None);
+    }
+
+    #[test]
+    fn test_blocks_reserved() {
+        let policy = UrlPolicy::default();
+        assert_eq!(blocked("http://240.0.0.1/", &policy), Some("reserved"));
+        assert_eq!(blocked("http://254.1.2.3/", &policy), Some("reserved"));
+    }
+
+    #[test]
+    fn test_blocks_nat64() {
+        let policy = UrlPolicy::default();
+        assert_eq!(
+            blocked("http://[64:ff9b::a9fe:a9fe]/", &policy),
+            Some("nat64")
+        );
+        assert_eq!(
+            blocked("http://[64:ff9b:1::7f00:1]/", &policy),
+            Some("nat64")
+        );
+    }
+
+    #[test]
+    fn test_blocks_ipv4_compatible() {
+        let policy = UrlPolicy::default();
+        assert_eq!(
+            blocked("http://[::127.0.0.2]/", &policy),
+            Some("ipv4-compatible")
+        );
+        assert_eq!(
+            blocked("http://[::5db8:d822]/", &policy),
+            Some("ipv4-compatible")
+        );
+    }
+
+    #[test]
+    fn test_blocks_6to4() {
+        let policy = UrlPolicy::default();
+        assert_eq!(blocked("http://[2002:a00:1::1]/", &policy), Some("6to4"));
+    }
+
+    #[test]
+    fn test_blocks_teredo() {
+        let policy = UrlPolicy::default();
+        assert_eq!(
+            blocked("http://[2001:0:4136:e378::1]/", &policy),
+            Some("teredo")
+        );
+    }
+
+    #[test]
+    fn test_allows_public_addresses() {
+        let policy = UrlPolicy::default();
+        assert_eq!(blocked("https://93.184.216.34/feed", &policy), None);
+        assert_eq!(blocked("https://[2606:4700::1111]/feed", &policy), None);
+    }
+
+    #[test]
+    fn test_allow_list_overrides() {
+        let policy = UrlPolicy::new("10.1.0.0/16, 127.0.0.1, wiki.internal");
+        assert_eq!(blocked("http://10.1.2.3/feed", &policy), None);
+        assert_eq!(blocked("http://127.0.0.1/feed", &policy), None);
+        assert_eq!(blocked("http://10.2.0.1/feed", &policy), Some("private"));
+        assert!(policy.host_allowed("Wiki.Internal"));
+    }
+}
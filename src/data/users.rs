@@ -0,0 +1,44 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+#[derive(Clone, Deserialize, Serialize, Debug, FromRow)]
+pub struct User {
+    pub id: i32,
+    pub github_id: i64,
+}
+
+/// The admin behind a request to the protected routes.
+#[derive(Clone, Debug)]
+pub struct Admin {
+    pub github_id: i64,
+}
+
+pub struct UserDataSource {
+    pool: PgPool,
+}
+
+impl UserDataSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Returns the user for a GitHub account, creating it on first sight.
+    pub async fn get_or_create_user(&self, github_id: i64) -> Result<User, anyhow::Error> {
+        let res = sqlx::query_as::<_, User>(
+            "INSERT INTO users (github_id)
+            VALUES ($1)
+            ON CONFLICT (github_id) DO UPDATE SET github_id = EXCLUDED.github_id
+            RETURNING id, github_id;",
+        )
+        .bind(github_id)
+        .fetch_one(&self.pool)
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context(format!("Failed to get user for GitHub id: {}", github_id))?;
+
+        Ok(res)
+    }
+}
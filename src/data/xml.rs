@@ -1,36 +1,298 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    net::IpAddr,
+};
+
 use anyhow::Context;
 use chrono::DateTime;
+use encoding_rs::{Encoding, UTF_8};
 use quickxml_to_serde::{xml_string_to_json, Config};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use reqwest::{
+    header::{CONTENT_TYPE, LOCATION},
+    redirect::Policy,
+    Client, Response,
+};
+
+use super::{
+    atom::atom_to_json, rss::rss_to_json, CachedEntry, CachedFeed, CredentialCipher,
+    FeedCredentials, FeedSyntax, Freshness, ParseOverrides, UrlPolicy,
+};
+
+const MAX_REDIRECTS: usize = 10;
+/// Longer entry bodies are cut off before they are cached and indexed
+const MAX_ENTRY_CONTENT_CHARS: usize = 100_000;
+
+#[derive(Clone, Debug)]
+pub struct FetchOptions {
+    pub max_bytes: usize,
+    pub url_policy: UrlPolicy,
+    pub credential_cipher: Option<CredentialCipher>,
+    /// Covers the whole request, including reading the body
+    pub timeout: std::time::Duration,
+    pub connect_timeout: std::time::Duration,
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    InvalidUrl { reason: String },
+    BlockedAddress { host: String, ip: IpAddr, class: &'static str },
+    TooManyRedirects,
+    UnexpectedStatus { status: u16 },
+    /// `received` is the byte count reached before giving up, or the
+    /// declared `Content-Length` when the body was never read.
+    BodyTooLarge { status: u16, limit: usize, received: u64 },
+    UnsupportedContentType { status: u16, content_type: String },
+}
+
+impl FetchError {
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            FetchError::UnexpectedStatus { status }
+            | FetchError::BodyTooLarge { status, .. }
+            | FetchError::UnsupportedContentType { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
 
-use super::{atom::atom_to_json, rss::rss_to_json, CachedEntry, CachedFeed};
+    pub fn bytes(&self) -> Option<u64> {
+        match self {
+            FetchError::BodyTooLarge { received, .. } => Some(*received),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FetchError::InvalidUrl { .. } => "invalid_url",
+            FetchError::BlockedAddress { .. } => "blocked_address",
+            FetchError::TooManyRedirects => "too_many_redirects",
+            FetchError::UnexpectedStatus { .. } => "unexpected_status",
+            FetchError::BodyTooLarge { .. } => "body_too_large",
+            FetchError::UnsupportedContentType { .. } => "unsupported_content_type",
+        }
+    }
+}
+
+impl Display for FetchError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FetchError::InvalidUrl { reason } => write!(f, "Invalid feed URL: {}", reason),
+            FetchError::BlockedAddress { host, ip, class } => {
+                write!(f, "Host '{}' resolves to blocked {} address {}", host, class, ip)
+            }
+            FetchError::TooManyRedirects => {
+                write!(f, "Exceeded {} redirects", MAX_REDIRECTS)
+            }
+            FetchError::UnexpectedStatus { status } => {
+                write!(f, "Unexpected HTTP status: {}", status)
+            }
+            FetchError::BodyTooLarge { limit, received, .. } => {
+                write!(f, "Response body of {} bytes exceeds limit of {} bytes", received, limit)
+            }
+            FetchError::UnsupportedContentType { content_type, .. } => {
+                write!(f, "Response content type is not a feed: {}", content_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+// Only reject content types that can never hold a feed; plenty of real feeds
+// are served as text/plain, application/octet-stream or without a header.
+fn is_feed_content_type(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    let blocked_prefixes = ["image/", "audio/", "video/", "font/", "multipart/"];
+    let blocked_types = [
+        "application/pdf",
+        "application/zip",
+        "application/gzip",
+        "application/x-tar",
+        "application/wasm",
+        "application/javascript",
+        "text/css",
+        "text/javascript",
+    ];
+
+    !blocked_prefixes.iter().any(|prefix| mime.starts_with(prefix))
+        && !blocked_types.contains(&mime.as_str())
+}
+
+// Decodes with the `charset` of the Content-Type, then the encoding named in
+// the XML declaration, falling back to UTF-8. A byte order mark wins over both.
+fn decode_body(body: &[u8], content_type: Option<&str>) -> String {
+    let header_charset = content_type.and_then(|content_type| {
+        content_type.split(';').skip(1).find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim()
+                .eq_ignore_ascii_case("charset")
+                .then(|| value.trim().trim_matches('"').to_string())
+        })
+    });
+    let encoding = header_charset
+        .or_else(|| xml_declared_encoding(body))
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+fn xml_declared_encoding(body: &[u8]) -> Option<String> {
+    let declaration = body.strip_prefix(b"<?xml")?;
+    let end = declaration.windows(2).position(|window| window == b"?>")?;
+    let declaration = std::str::from_utf8(&declaration[..end]).ok()?;
+    let (_, rest) = declaration.split_once("encoding")?;
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    Some(value[..value.find(quote)?].to_string())
+}
+
+pub struct XmlResponse {
+    pub status: u16,
+    pub body: String,
+}
 
 pub struct XmlDataSource;
 
 impl XmlDataSource {
-    pub async fn get(url: &str) -> Result<String, anyhow::Error> {
-        reqwest::get(url)
-            .await
-            .inspect_err(|e| { eprintln!("GET request error: {:?}", e) })
-            .context("Failed to request feed data")?
-            .text()
+    // Redirects are followed by hand so every hop is checked against the URL
+    // policy, and each request is pinned to the addresses that were checked.
+    // Feed credentials are only sent to the origin they were configured for.
+    async fn send(
+        url: &str,
+        options: &FetchOptions,
+        credentials: Option<&FeedCredentials>,
+    ) -> Result<Response, anyhow::Error> {
+        let mut url = options.url_policy.parse(url)?;
+        let origin = url.origin();
+
+        for _ in 0..=MAX_REDIRECTS {
+            let addrs = options.url_policy.resolve(&url).await?;
+            let client = Client::builder()
+                .redirect(Policy::none())
+                .timeout(options.timeout)
+                .connect_timeout(options.connect_timeout)
+                .resolve_to_addrs(url.host_str().unwrap_or_default(), &addrs)
+                .build()
+                .context("Failed to build HTTP client")?;
+
+            let mut request = client.get(url.clone());
+            if let Some(credentials) = credentials.filter(|_| url.origin() == origin) {
+                request = credentials.apply(request);
+            }
+
+            // The URL can carry decrypted credentials, so it is kept out of
+            // errors that get logged and stored
+            let response = request
+                .send()
+                .await
+                .map_err(reqwest::Error::without_url)
+                .inspect_err(|e| { eprintln!("GET request error: {:?}", e) })
+                .context("Failed to request feed data")?;
+
+            if !response.status().is_redirection() {
+                return Ok(response);
+            }
+
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .ok_or(FetchError::UnexpectedStatus {
+                    status: response.status().as_u16(),
+                })?;
+            let next = url.join(location).map_err(|e| FetchError::InvalidUrl {
+                reason: e.to_string(),
+            })?;
+            url = options.url_policy.parse(next.as_str())?;
+        }
+
+        Err(FetchError::TooManyRedirects.into())
+    }
+
+    pub async fn get(
+        url: &str,
+        options: &FetchOptions,
+        credentials: Option<&FeedCredentials>,
+    ) -> Result<XmlResponse, anyhow::Error> {
+        let mut response = Self::send(url, options, credentials).await?;
+
+        let status = response.status().as_u16();
+        if !response.status().is_success() {
+            return Err(FetchError::UnexpectedStatus { status }.into());
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        if let Some(content_type) = &content_type {
+            if !is_feed_content_type(content_type) {
+                return Err(FetchError::UnsupportedContentType {
+                    status,
+                    content_type: content_type.clone(),
+                }
+                .into());
+            }
+        }
+
+        let too_large = |received: u64| FetchError::BodyTooLarge {
+            status,
+            limit: options.max_bytes,
+            received,
+        };
+        if let Some(length) = response
+            .content_length()
+            .filter(|length| *length > options.max_bytes as u64)
+        {
+            return Err(too_large(length).into());
+        }
+
+        let mut body: Vec<u8> = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
             .await
+            .map_err(reqwest::Error::without_url)
             .inspect_err(|e| { eprintln!("XML response parsing error: {:?}", e) })
-            .context("Failed to parse xml response")
+            .context("Failed to read xml response")?
+        {
+            if body.len() + chunk.len() > options.max_bytes {
+                return Err(too_large((body.len() + chunk.len()) as u64).into());
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(XmlResponse {
+            status,
+            body: decode_body(&body, content_type.as_deref()),
+        })
     }
 
-    pub fn parse_xml_string(xml_string: &str, name: &str, category: &str) -> Result<CachedFeed, anyhow::Error> {
+    pub fn parse_xml_string(xml_string: &str, name: &str, category: &str, overrides: &ParseOverrides) -> Result<CachedFeed, anyhow::Error> {
         if xml_string.contains("<rss") {
-            parse_rss(xml_string, name, category)
+            parse_rss(xml_string, name, category, overrides)
         } else if xml_string.contains("<feed") {
-            parse_atom(xml_string, name, category)
+            parse_atom(xml_string, name, category, overrides)
         } else {
             anyhow::bail!("Unknown feed syntax".to_string())
         }
     }
 }
 
-fn parse_atom(xml_string: &str, name: &str, category: &str) -> Result<CachedFeed, anyhow::Error> {
-    let value = xml_string_to_json(xml_string.into(), &Config::new_with_defaults())?;
+fn parse_atom(xml_string: &str, name: &str, category: &str, overrides: &ParseOverrides) -> Result<CachedFeed, anyhow::Error> {
+    let mut value = xml_string_to_json(xml_string.into(), &Config::new_with_defaults())?;
+    overrides.apply(FeedSyntax::Atom, &mut value);
     let json = atom_to_json(value)?;
 
     let entries = json.feed.entry.into_iter().map(|entry| CachedEntry {
@@ -38,29 +300,155 @@ fn parse_atom(xml_string: &str, name: &str, category: &str) -> Result<CachedFeed
         url: entry.link,
         created_date: entry.published
             .or(entry.updated)
-            .unwrap_or(DateTime::UNIX_EPOCH)
+            .unwrap_or(DateTime::UNIX_EPOCH),
+        read: None,
+        content: entry.content.or(entry.summary).map(truncate_content),
     }).collect();
 
     Ok(CachedFeed {
         name: name.into(),
         category: category.into(),
-        entries
+        entries,
+        freshness: Freshness::default(),
+        tags: Vec::new(),
+        unread_count: None,
     })
 }
 
-fn parse_rss(xml_string: &str, name: &str, category: &str) -> Result<CachedFeed, anyhow::Error> {
-    let value = xml_string_to_json(xml_string.into(), &Config::new_with_defaults())?;
+fn parse_rss(xml_string: &str, name: &str, category: &str, overrides: &ParseOverrides) -> Result<CachedFeed, anyhow::Error> {
+    let mut value = xml_string_to_json(xml_string.into(), &Config::new_with_defaults())?;
+    overrides.apply(FeedSyntax::Rss, &mut value);
     let json = rss_to_json(value)?;
 
     let entries = json.rss.channel.item.into_iter().map(|entry| CachedEntry {
         title: entry.title,
         url: entry.link,
-        created_date: entry.pub_date
+        created_date: entry.pub_date,
+        read: None,
+        content: entry.encoded.or(entry.description).map(truncate_content),
     }).collect();
 
     Ok(CachedFeed {
         name: name.into(),
         category: category.into(),
-        entries
+        entries,
+        freshness: Freshness::default(),
+        tags: Vec::new(),
+        unread_count: None,
     })
 }
+
+fn truncate_content(content: String) -> String {
+    match content.char_indices().nth(MAX_ENTRY_CONTENT_CHARS) {
+        Some((end, _)) => content[..end].to_string(),
+        None => content,
+    }
+}
+
+/// Reads the text of an optional element that may be plain, typed
+/// (`{"@type": "html", "#text": ...}`) or repeated. Anything else is ignored.
+pub(super) fn optional_text<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    fn text(value: &Value) -> Option<String> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Object(map) => map.get("#text").and_then(text),
+            Value::Array(values) => values.iter().find_map(text),
+            Value::Bool(_) | Value::Null => None,
+        }
+    }
+
+    Ok(text(&Value::deserialize(deserializer)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_content_types_allowed() {
+        assert!(is_feed_content_type("application/rss+xml"));
+        assert!(is_feed_content_type("application/atom+xml; charset=utf-8"));
+        assert!(is_feed_content_type("text/xml"));
+        assert!(is_feed_content_type("text/html; charset=UTF-8"));
+        assert!(is_feed_content_type("application/octet-stream"));
+    }
+
+    #[test]
+    fn test_non_feed_content_types_rejected() {
+        assert!(!is_feed_content_type("image/png"));
+        assert!(!is_feed_content_type("Video/MP4"));
+        assert!(!is_feed_content_type("audio/mpeg"));
+        assert!(!is_feed_content_type("application/zip"));
+        assert!(!is_feed_content_type("application/pdf; version=1.7"));
+    }
+
+    #[test]
+    fn test_rss_content_prefers_encoded() {
+        let xml = r#"<rss><channel>
+            <item>
+                <title>First</title>
+                <link>https://example.com/first</link>
+                <pubDate>Tue, 03 Sep 2024 13:51:48 GMT</pubDate>
+                <description>Short summary</description>
+                <content:encoded><![CDATA[<p>Full body</p>]]></content:encoded>
+            </item>
+            <item>
+                <title>Second</title>
+                <link>https://example.com/second</link>
+                <pubDate>Tue, 03 Sep 2024 13:51:48 GMT</pubDate>
+                <description>Only a summary</description>
+            </item>
+        </channel></rss>"#;
+
+        let feed = XmlDataSource::parse_xml_string(xml, "rss", "test", &ParseOverrides::default()).unwrap();
+        assert_eq!(feed.entries[0].content.as_deref(), Some("<p>Full body</p>"));
+        assert_eq!(feed.entries[1].content.as_deref(), Some("Only a summary"));
+    }
+
+    #[test]
+    fn test_atom_content_reads_typed_text() {
+        let xml = r#"<feed>
+            <entry>
+                <title>First</title>
+                <link href="https://example.com/first" type="text/html"/>
+                <updated>2024-10-09T18:55:25+00:00</updated>
+                <summary>Short summary</summary>
+                <content type="html">&lt;p&gt;Full body&lt;/p&gt;</content>
+            </entry>
+            <entry>
+                <title>Second</title>
+                <link href="https://example.com/second" type="text/html"/>
+                <updated>2024-10-09T18:55:25+00:00</updated>
+            </entry>
+        </feed>"#;
+
+        let feed = XmlDataSource::parse_xml_string(xml, "atom", "test", &ParseOverrides::default()).unwrap();
+        assert_eq!(feed.entries[0].content.as_deref(), Some("<p>Full body</p>"));
+        assert_eq!(feed.entries[1].content, None);
+    }
+
+    #[test]
+    fn test_truncate_content() {
+        let long = "é".repeat(MAX_ENTRY_CONTENT_CHARS + 5);
+        assert_eq!(truncate_content(long).chars().count(), MAX_ENTRY_CONTENT_CHARS);
+        assert_eq!(truncate_content("short".to_string()), "short");
+    }
+
+    #[test]
+    fn test_decode_body_uses_charset() {
+        let latin1 = b"<rss><title>Caf\xe9</title></rss>";
+        assert_eq!(
+            decode_body(latin1, Some("application/rss+xml; charset=ISO-8859-1")),
+            "<rss><title>Café</title></rss>"
+        );
+
+        let declared = b"<?xml version=\"1.0\" encoding='windows-1252'?><rss>\x80</rss>";
+        assert!(decode_body(declared, Some("text/xml")).ends_with("<rss>€</rss>"));
+
+        assert_eq!(decode_body("é".as_bytes(), None), "é");
+    }
+}
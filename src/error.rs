@@ -1,18 +1,108 @@
+use std::fmt::{self, Display, Formatter};
+
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
 
+use crate::data::FetchError;
+
+/// An error caused by the request rather than by the service.
+#[derive(Debug)]
+pub enum ClientError {
+    BadRequest(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+}
+
+impl ClientError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ClientError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ClientError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ClientError::NotFound(_) => StatusCode::NOT_FOUND,
+            ClientError::Conflict(_) => StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ClientError::BadRequest(message)
+            | ClientError::Forbidden(message)
+            | ClientError::NotFound(message)
+            | ClientError::Conflict(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// Whether `err` was caused by a unique constraint, e.g. a duplicate name.
+pub fn is_unique_violation(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<sqlx::Error>(),
+            Some(sqlx::Error::Database(e)) if e.is_unique_violation()
+        )
+    })
+}
+
+/// Whether `err` means the thing being created already exists, either as a
+/// unique violation or as a conflict found beforehand.
+pub fn is_conflict(err: &anyhow::Error) -> bool {
+    is_unique_violation(err)
+        || err.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<ClientError>(),
+                Some(ClientError::Conflict(_))
+            )
+        })
+}
+
 #[derive(Debug)]
 pub struct ServiceError(anyhow::Error);
 
+impl ServiceError {
+    /// Errors caused by the request itself are reported with their message,
+    /// everything else is an internal error.
+    fn status(&self) -> StatusCode {
+        for cause in self.0.chain() {
+            if let Some(client_error) = cause.downcast_ref::<ClientError>() {
+                return client_error.status();
+            }
+            if let Some(fetch_error) = cause.downcast_ref::<FetchError>() {
+                return match fetch_error {
+                    FetchError::InvalidUrl { .. } | FetchError::BlockedAddress { .. } => {
+                        StatusCode::BAD_REQUEST
+                    }
+                    _ => StatusCode::BAD_GATEWAY,
+                };
+            }
+        }
+        if is_unique_violation(&self.0) {
+            return StatusCode::CONFLICT;
+        }
+
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
 impl IntoResponse for ServiceError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Internal server error: {}", self.0),
-        )
-            .into_response()
+        let status = self.status();
+        if status == StatusCode::INTERNAL_SERVER_ERROR {
+            return (status, format!("Internal server error: {}", self.0)).into_response();
+        }
+
+        // The database's own message names tables and constraints
+        if status == StatusCode::CONFLICT && is_unique_violation(&self.0) {
+            return (status, format!("{}: already exists", self.0)).into_response();
+        }
+
+        (status, format!("{:#}", self.0)).into_response()
     }
 }
 
@@ -24,3 +114,60 @@ where
         Self(err.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_rejected_urls_are_bad_requests() {
+        let blocked: Result<(), FetchError> = Err(FetchError::BlockedAddress {
+            host: "localhost".to_string(),
+            ip: "127.0.0.1".parse().unwrap(),
+            class: "loopback",
+        });
+        let error = ServiceError::from(blocked.context("Failed to add feed").unwrap_err());
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+
+        let error = ServiceError::from(FetchError::InvalidUrl {
+            reason: "relative URL without a base".to_string(),
+        });
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+
+        let error = ServiceError::from(anyhow::Error::msg("Database error"));
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_client_errors_keep_their_status() {
+        let bad_request: Result<(), ClientError> =
+            Err(ClientError::BadRequest("A name is needed".to_string()));
+        let error = ServiceError::from(bad_request.context("Failed to subscribe").unwrap_err());
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+
+        let error = ServiceError::from(ClientError::Forbidden("Admins only".to_string()));
+        assert_eq!(error.status(), StatusCode::FORBIDDEN);
+
+        let error = ServiceError::from(ClientError::NotFound("Unknown category".to_string()));
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+
+        let error = ServiceError::from(ClientError::Conflict("Already exists".to_string()));
+        assert_eq!(error.status(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let conflict: Result<(), ClientError> = Err(ClientError::Conflict(
+            "Deleted feed 1 already uses this URL".to_string(),
+        ));
+        assert!(is_conflict(
+            &conflict.context("Failed to create feed").unwrap_err()
+        ));
+
+        let forbidden = anyhow::Error::from(ClientError::Forbidden("Admins only".to_string()));
+        assert!(!is_conflict(&forbidden));
+        assert!(!is_conflict(&anyhow::Error::msg("Database error")));
+    }
+}
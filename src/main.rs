@@ -1,7 +1,7 @@
 use anyhow::Context;
 use axum::{
     middleware,
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
 use shuttle_runtime::SecretStore;
@@ -9,12 +9,19 @@ use sqlx::PgPool;
 
 mod service;
 use service::{
-    batch_create_raw_feeds, create_raw_feed, delete_raw_feed, get_categories, get_feeds,
-    get_raw_feeds, schedule_cache_refresh, update_raw_feed,
+    batch_create_raw_feeds, blogroll_public, create_category, create_raw_feed, delete_raw_feed,
+    delete_retention_rule, export_opml, get_audit_log, get_blogroll, get_catalog, get_categories,
+    get_category_details, get_deleted_raw_feeds, get_feeds, get_fetch_logs, get_raw_feeds,
+    get_readiness, get_response_cache_stats, get_retention_rules, get_starred, get_subscriptions,
+    get_tags, import_opml, listen_for_invalidations, mark_entries_read, mark_read,
+    merge_categories, prune_retention, reorder_categories, restore_raw_feed,
+    schedule_cache_refresh, search_entries, set_raw_feed_status, set_retention_rule, star_entry,
+    subscribe, unstar_entry, unsubscribe, update_category, update_raw_feed, update_subscription,
+    warm_up_deadline, Readiness, RefreshService, ResponseCache,
 };
 
 mod auth;
-use auth::auth_middleware;
+use auth::{auth_middleware, optional_user_middleware, user_middleware, TokenCache};
 
 mod data;
 mod error;
@@ -32,30 +39,106 @@ pub async fn rss_reader_service(
         .await
         .expect("Migration failed...");
 
+    let responses = ResponseCache::new(pool.clone(), &secrets)
+        .expect("Invalid response cache configuration...");
+    let refresh = RefreshService::new(pool.clone(), &secrets, responses.clone())
+        .expect("Invalid feed fetch configuration...");
+    let warm_up_deadline = warm_up_deadline(&secrets).expect("Invalid warm-up configuration...");
+    let tokens = TokenCache::new(&secrets).expect("Invalid auth configuration...");
+    let blogroll_public = blogroll_public(&secrets).expect("Invalid blogroll configuration...");
+    let readiness = Readiness::default();
+
     let state = AppState {
         pool: pool.clone(),
         secrets: secrets.clone(),
+        refresh: refresh.clone(),
+        responses: responses.clone(),
+        readiness: readiness.clone(),
+        tokens,
+        blogroll_public,
     };
 
     let scheduler_pool = pool.clone();
     let scheduler_secrets = secrets.clone();
+    let scheduler_readiness = readiness.clone();
+    tokio::spawn(async move {
+        let _ = schedule_cache_refresh(
+            scheduler_pool,
+            refresh,
+            scheduler_readiness,
+            warm_up_deadline,
+            &scheduler_secrets,
+        )
+        .await
+        .inspect_err(|e| {
+            eprintln!("Failed to schedule cache clear: {}", e);
+        })
+        .context("Failed to schedule cache clear");
+    });
+
+    let listener_pool = pool.clone();
     tokio::spawn(async move {
-        let _ = schedule_cache_refresh(scheduler_pool, &scheduler_secrets)
+        let _ = listen_for_invalidations(listener_pool, responses)
             .await
             .inspect_err(|e| {
-                eprintln!("Failed to schedule cache clear: {}", e);
-            })
-            .context("Failed to schedule cache clear");
+                eprintln!("Failed to listen for cache invalidations: {}", e);
+            });
     });
 
     let unprotected_routes = Router::new()
-        .route("/feeds", get(get_feeds))
-        .route("/categories", get(get_categories));
+        .route("/ready", get(get_readiness))
+        .route(
+            "/feeds",
+            get(get_feeds).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                optional_user_middleware,
+            )),
+        )
+        .route("/categories", get(get_categories))
+        .route("/categories/details", get(get_category_details))
+        .route("/tags", get(get_tags))
+        .route("/search", get(search_entries))
+        .route("/blogroll.opml", get(get_blogroll));
+
+    let user_routes = Router::new()
+        .route("/me/read", post(mark_read))
+        .route("/me/read/entries", post(mark_entries_read))
+        .route("/catalog", get(get_catalog))
+        .route("/subscriptions", get(get_subscriptions).post(subscribe))
+        .route(
+            "/subscriptions/:id",
+            post(update_subscription).delete(unsubscribe),
+        )
+        .route(
+            "/starred",
+            get(get_starred).post(star_entry).delete(unstar_entry),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            user_middleware,
+        ));
 
     let protected_routes = Router::new()
         .route("/admin", get(get_raw_feeds).post(create_raw_feed))
+        .route("/admin/audit", get(get_audit_log))
         .route("/admin/batch", post(batch_create_raw_feeds))
+        .route("/admin/deleted", get(get_deleted_raw_feeds))
+        .route("/admin/opml", get(export_opml).post(import_opml))
+        .route("/admin/cache", get(get_response_cache_stats))
+        .route("/admin/categories", post(create_category))
+        .route("/admin/categories/order", post(reorder_categories))
+        .route("/admin/categories/:id", post(update_category))
+        .route("/admin/categories/:id/merge", post(merge_categories))
+        .route(
+            "/admin/retention",
+            get(get_retention_rules).post(set_retention_rule),
+        )
+        .route("/admin/retention/prune", post(prune_retention))
+        .route("/admin/retention/:id", delete(delete_retention_rule))
         .route("/admin/:id", post(update_raw_feed).delete(delete_raw_feed))
+        .route("/admin/:id/fetches", get(get_fetch_logs))
+        .route("/admin/:id/status", post(set_raw_feed_status))
+        .route("/admin/:id/restore", post(restore_raw_feed))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
@@ -63,6 +146,7 @@ pub async fn rss_reader_service(
 
     let routes = Router::new()
         .merge(unprotected_routes)
+        .merge(user_routes)
         .merge(protected_routes)
         .with_state(state);
 
@@ -73,4 +157,9 @@ pub async fn rss_reader_service(
 struct AppState {
     pool: PgPool,
     secrets: SecretStore,
+    refresh: RefreshService,
+    responses: ResponseCache,
+    readiness: Readiness,
+    tokens: TokenCache,
+    blogroll_public: bool,
 }
@@ -0,0 +1,80 @@
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Postgres, Transaction};
+
+use crate::{
+    data::{Admin, AuditDataSource, AuditInput},
+    error::ServiceError,
+    AppState,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct AuditParam {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Starts the transaction an admin change is made in, together with its audit
+/// entry. Side effects such as invalidating responses wait for `commit_change`.
+pub async fn begin_change(
+    state: &AppState,
+) -> Result<Transaction<'static, Postgres>, anyhow::Error> {
+    state
+        .pool
+        .begin()
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to start transaction")
+}
+
+pub async fn commit_change(tx: Transaction<'static, Postgres>) -> Result<(), anyhow::Error> {
+    tx.commit()
+        .await
+        .inspect_err(|e| {
+            eprintln!("Database error: {:?}", e);
+        })
+        .context("Failed to commit transaction")
+}
+
+/// Records an admin change on the change's own transaction, so a change that
+/// can't be audited is rolled back with it.
+pub async fn audit(
+    conn: &mut PgConnection,
+    admin: &Admin,
+    action: &str,
+    target_id: Option<i32>,
+    before: impl Serialize,
+    after: impl Serialize,
+) -> Result<(), anyhow::Error> {
+    let input = AuditInput {
+        github_id: admin.github_id,
+        action: action.to_string(),
+        target_id,
+        before: serde_json::to_value(before)
+            .with_context(|| format!("Failed to serialize audit entry: {}", action))?,
+        after: serde_json::to_value(after)
+            .with_context(|| format!("Failed to serialize audit entry: {}", action))?,
+    };
+
+    AuditDataSource::record(conn, input).await
+}
+
+pub async fn get_audit_log(
+    State(state): State<AppState>,
+    Query(params): Query<AuditParam>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let entries = AuditDataSource::new(state.pool.clone())
+        .get_audit_log(
+            params.limit.unwrap_or(50).clamp(1, 200),
+            params.offset.unwrap_or(0).max(0),
+        )
+        .await?;
+    Ok(Json(entries))
+}
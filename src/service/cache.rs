@@ -1,23 +1,33 @@
 use anyhow::Context;
-use axum::extract::{Query, State};
+use chrono::Utc;
 use shuttle_runtime::SecretStore;
 use sqlx::PgPool;
 use tokio::time::{self, Duration};
 
 use crate::{
-    data::CacheDataSource,
-    service::{get_feeds, FeedsParam},
-    AppState,
+    data::{FeedDataSource, FetchLogDataSource, LeaderDataSource, LeaderLock, RetentionDataSource},
+    service::{retention_batch_size, warm_up, Readiness, RefreshService},
 };
 
+/// Runs the refresh job on whichever instance holds the leader lock. The
+/// first tick is immediate and, on the leader, is the startup warm-up.
 pub async fn schedule_cache_refresh(
     pool: PgPool,
+    refresh: RefreshService,
+    readiness: Readiness,
+    warm_up_deadline: Duration,
     secrets: &SecretStore,
 ) -> Result<(), anyhow::Error> {
-    let cache_duration: i32 = SecretStore::get(secrets, "CACHE_DURATION_MINS")
-        .context("Missing expected ENV_VAR: CACHE_DURATION_MINS")?
+    let cache_duration = refresh.cache_duration();
+    let fetch_log_retention_days: i32 = SecretStore::get(secrets, "FETCH_LOG_RETENTION_DAYS")
+        .unwrap_or_else(|| "14".to_string())
         .parse::<i32>()
-        .context("CACHE_DURATION_MINS is not a valid integer")?;
+        .context("FETCH_LOG_RETENTION_DAYS is not a valid integer")?;
+    let feed_purge_after_days: i32 = SecretStore::get(secrets, "FEED_PURGE_AFTER_DAYS")
+        .unwrap_or_else(|| "30".to_string())
+        .parse::<i32>()
+        .context("FEED_PURGE_AFTER_DAYS is not a valid integer")?;
+    let retention_batch_size = retention_batch_size(secrets)?;
 
     println!(
         "Scheduling cache refresh job for once every [{}] mins",
@@ -26,27 +36,174 @@ pub async fn schedule_cache_refresh(
 
     let mut interval = time::interval(Duration::from_secs(cache_duration as u64 * 60));
 
-    let cache = CacheDataSource::new(pool.clone());
+    let feeds = FeedDataSource::new(pool.clone());
+    let fetch_log = FetchLogDataSource::new(pool.clone());
+    let retention = RetentionDataSource::new(pool.clone());
+    let leader = LeaderDataSource::new(pool.clone());
+    let mut leader_lock: Option<LeaderLock> = None;
+    let mut pauses_checked_at = Utc::now();
     loop {
         interval.tick().await;
-        println!("Attempting to refresh cache");
-        cache
-            .cache_clear(cache_duration)
-            .await
-            .inspect_err(|e| {
-                eprintln!("Database error: {}", e);
-            })
-            .context("Failed to clear cache")?;
-        let _ = get_feeds(
-            State(AppState {
-                pool: pool.clone(),
-                secrets: secrets.clone(),
-            }),
-            Query(FeedsParam {
-                duration: None,
-                max_entries: None,
-            }),
+
+        // Only the instance holding the leader lock refreshes and prunes;
+        // the others keep trying in case the leader goes away.
+        if let Some(lock) = leader_lock.as_mut() {
+            if !lock.is_held().await {
+                println!("Lost scheduler leadership");
+                leader_lock = None;
+            }
+        }
+        if leader_lock.is_none() {
+            match leader.try_acquire().await {
+                Ok(Some(lock)) => {
+                    println!("Acquired scheduler leadership");
+                    leader_lock = Some(lock);
+                }
+                Ok(None) => {
+                    // The leader keeps the shared cache warm for everyone
+                    readiness.mark_ready();
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Failed to elect scheduler leader: {:?}", e);
+                    readiness.mark_ready();
+                    continue;
+                }
+            }
+        }
+
+        if !readiness.is_ready() {
+            warm_up(&refresh, &readiness, warm_up_deadline).await;
+        } else {
+            // Pauses that ended since the last tick, in case the instance
+            // that would have resumed them went away
+            let checked_at = Utc::now();
+            match feeds.get_resumed_raw_feeds(pauses_checked_at).await {
+                Ok(resumed) if !resumed.is_empty() => {
+                    println!("Resuming [{}] feeds after their pause", resumed.len());
+                    refresh.refresh_feeds(resumed).await;
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to get resumed feeds: {:?}", e),
+            }
+            pauses_checked_at = checked_at;
+
+            println!("Attempting to refresh cache");
+            refresh
+                .refresh_stale_feeds()
+                .await
+                .inspect_err(|e| {
+                    eprintln!("Database error: {}", e);
+                })
+                .context("Failed to refresh cache")?;
+        }
+
+        match retention.prune_entries(retention_batch_size).await {
+            Ok(report) if report.entries_removed > 0 => {
+                println!(
+                    "Pruned [{}] entries from [{}] feeds",
+                    report.entries_removed, report.feeds_pruned
+                );
+                refresh.responses().invalidate();
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to prune cached entries: {:?}", e),
+        }
+
+        match feeds.purge_deleted_raw_feeds(feed_purge_after_days).await {
+            Ok(purged) if purged > 0 => println!("Purged [{}] deleted feeds", purged),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to purge deleted feeds: {:?}", e),
+        }
+
+        match fetch_log.prune(fetch_log_retention_days).await {
+            Ok(pruned) if pruned > 0 => println!("Pruned [{}] fetch log rows", pruned),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to prune fetch log: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{FeedServer, ParseOverrides, RawFeedInput, ScratchDatabase, SCHEDULER_LOCK},
+        service::ResponseCache,
+    };
+
+    fn secrets() -> SecretStore {
+        serde_json::from_value(serde_json::json!({
+            "CACHE_DURATION_MINS": "30",
+            "FEED_URL_ALLOW_LIST": "127.0.0.1",
+        }))
+        .unwrap()
+    }
+
+    /// Starts an instance's scheduler and waits for it to report ready.
+    async fn start_instance(pool: &PgPool) -> tokio::task::JoinHandle<()> {
+        let secrets = secrets();
+        let responses = ResponseCache::new(pool.clone(), &secrets).unwrap();
+        let refresh = RefreshService::new(pool.clone(), &secrets, responses).unwrap();
+        let readiness = Readiness::default();
+
+        let pool = pool.clone();
+        let ready = readiness.clone();
+        let scheduler = tokio::spawn(async move {
+            let deadline = Duration::from_secs(10);
+            let _ = schedule_cache_refresh(pool, refresh, ready, deadline, &secrets).await;
+        });
+
+        for _ in 0..100 {
+            if readiness.is_ready() {
+                break;
+            }
+            time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(readiness.is_ready());
+
+        scheduler
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_only_leader_warms_up() {
+        let _scheduler = SCHEDULER_LOCK.lock().await;
+        let database = ScratchDatabase::new("test_only_leader_warms_up").await;
+        let server = FeedServer::start().await;
+        let mut conn = database.pool.acquire().await.unwrap();
+        FeedDataSource::create_raw_feed(
+            &mut conn,
+            RawFeedInput {
+                name: "feed".to_string(),
+                url: server.url.clone(),
+                category: "news".to_string(),
+                parse_overrides: ParseOverrides::default(),
+                credentials: None,
+                tags: None,
+            },
+            None,
         )
-        .await;
+        .await
+        .unwrap();
+        drop(conn);
+
+        // The leader is only ready once its warm-up has fetched the stale feed
+        let leader = start_instance(&database.pool).await;
+        assert_eq!(server.hits(), 1);
+
+        // Another instance is ready straight away and leaves fetching to the leader
+        sqlx::query("UPDATE cached_feeds SET last_refreshed_at = NOW() - INTERVAL '1 hour'")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let standby = start_instance(&database.pool).await;
+        assert_eq!(server.hits(), 1);
+
+        for scheduler in [leader, standby] {
+            scheduler.abort();
+            let _ = scheduler.await;
+        }
+        database.drop().await;
     }
 }
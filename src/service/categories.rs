@@ -0,0 +1,122 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{
+    data::{Admin, CategoryDataSource, CategoryInput, CategoryMergeInput, CategoryOrderInput},
+    error::ServiceError,
+    service::{audit, begin_change, commit_change},
+    AppState,
+};
+
+/// Category names in display order.
+pub async fn get_categories(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let datasource = CategoryDataSource::new(state.pool.clone());
+    let body = state
+        .responses
+        .get_or_load("categories".to_string(), async {
+            let categories = datasource.get_categories().await?;
+            Ok(categories
+                .into_iter()
+                .map(|category| category.name)
+                .collect::<Vec<_>>())
+        })
+        .await?;
+
+    Ok(Json(body))
+}
+
+/// Categories with their metadata, in display order.
+pub async fn get_category_details(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let body = state
+        .responses
+        .get_or_load(
+            "categories/details".to_string(),
+            CategoryDataSource::new(state.pool.clone()).get_categories(),
+        )
+        .await?;
+
+    Ok(Json(body))
+}
+
+pub async fn create_category(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    Json(body): Json<CategoryInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let mut tx = begin_change(&state).await?;
+    let category = CategoryDataSource::create_category(&mut tx, body).await?;
+    audit(
+        &mut tx,
+        &admin,
+        "category.create",
+        Some(category.id),
+        (),
+        &category,
+    )
+    .await?;
+    commit_change(tx).await?;
+    state.responses.invalidate();
+    Ok(Json(category))
+}
+
+pub async fn update_category(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<i32>,
+    Json(body): Json<CategoryInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let mut tx = begin_change(&state).await?;
+    let before = CategoryDataSource::get_category(&mut tx, id).await?;
+    CategoryDataSource::update_category(&mut tx, id, body).await?;
+    let after = CategoryDataSource::get_category(&mut tx, id).await?;
+    audit(&mut tx, &admin, "category.update", Some(id), before, after).await?;
+    commit_change(tx).await?;
+    state.responses.invalidate();
+    Ok(())
+}
+
+pub async fn reorder_categories(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    Json(body): Json<CategoryOrderInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let mut tx = begin_change(&state).await?;
+    let before = CategoryDataSource::fetch_categories(&mut tx).await?;
+    CategoryDataSource::reorder_categories(&mut tx, body).await?;
+    let after = CategoryDataSource::fetch_categories(&mut tx).await?;
+    audit(&mut tx, &admin, "category.reorder", None, before, after).await?;
+    commit_change(tx).await?;
+    state.responses.invalidate();
+    Ok(())
+}
+
+pub async fn merge_categories(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<i32>,
+    Json(body): Json<CategoryMergeInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let into = body.into;
+    let mut tx = begin_change(&state).await?;
+    let before = CategoryDataSource::get_category(&mut tx, id).await?;
+    let report = CategoryDataSource::merge_categories(&mut tx, id, body).await?;
+    audit(
+        &mut tx,
+        &admin,
+        "category.merge",
+        Some(id),
+        before,
+        serde_json::json!({ "into": into, "feeds_moved": report.feeds_moved }),
+    )
+    .await?;
+    commit_change(tx).await?;
+    state.responses.invalidate();
+    Ok(Json(report))
+}
@@ -1,116 +1,193 @@
+use anyhow::Context;
 use axum::{
     extract::{Path, Query, State},
     response::IntoResponse,
-    Json,
+    Extension, Json,
 };
-use futures::future;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     data::{
-        CacheDataSource, CachedFeed, Duration, FeedDataSource, RawFeed, RawFeedInput, XmlDataSource,
+        Admin, CacheDataSource, CachedFeed, Duration, FeedCredentials, FeedDataSource,
+        FeedStatusInput, FetchLogDataSource, FetchOptions, RawFeedInput, TagFilter, User,
     },
-    error::ServiceError,
+    error::{ClientError, ServiceError},
+    service::{audit, begin_change, commit_change},
     AppState,
 };
 
-#[derive(Deserialize, Serialize, Debug)]
-struct Entry {
-    title: String,
-    url: String,
-    created_date: String,
+#[derive(Deserialize, Debug)]
+pub struct FeedsParam {
+    pub duration: Option<Duration>,
+    /// Clamped to 1..=`MAX_ENTRIES_PER_FEED`
+    pub max_entries: Option<usize>,
+    /// Needs a signed-in user
+    pub unread_only: Option<bool>,
+    /// Comma separated, at most `MAX_FILTER_TAGS`
+    pub tags: Option<String>,
+    pub tag_match: Option<TagMatch>,
+}
+
+/// Anonymous `/feeds` responses are cached per query, so the parts of the query
+/// a caller controls are bounded.
+const MAX_ENTRIES_PER_FEED: usize = 50;
+const MAX_FILTER_TAGS: usize = 10;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+/// What `/feeds` caches: the feeds plus which of them are stale, so stale
+/// feeds keep being revalidated while a cached response is served.
+#[derive(Serialize, Debug)]
+struct FeedsBody {
+    feeds: Vec<CachedFeed>,
+    stale: Vec<StaleFeed>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
-struct Feed {
-    name: String,
-    category: String,
-    entries: Vec<Entry>,
+struct StaleFeed {
+    raw_feed_id: i32,
+    last_attempt_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize, Debug)]
-pub struct FeedsParam {
-    pub duration: Option<Duration>,
-    pub max_entries: Option<usize>,
+pub struct FetchLogParam {
+    pub limit: Option<i64>,
+}
+
+fn encrypt_credentials(
+    options: &FetchOptions,
+    credentials: &FeedCredentials,
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    if credentials.is_empty() {
+        return Ok(None);
+    }
+
+    credentials.validate()?;
+    let cipher = options
+        .credential_cipher
+        .as_ref()
+        .context("Missing expected ENV_VAR: FEED_CREDENTIALS_KEY")?;
+    Ok(Some(cipher.encrypt(credentials)?))
+}
+
+/// Reads the comma separated `tags` param. An empty list filters nothing,
+/// like leaving it out.
+fn tag_filter(tags: Option<&str>, tag_match: TagMatch) -> Option<TagFilter> {
+    let mut tags: Vec<String> = tags?
+        .split(',')
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+    if tags.is_empty() {
+        return None;
+    }
+
+    Some(TagFilter {
+        tags,
+        match_all: tag_match == TagMatch::All,
+    })
 }
 
 #[axum::debug_handler]
 pub async fn get_feeds(
     State(state): State<AppState>,
+    user: Option<Extension<User>>,
     Query(params): Query<FeedsParam>,
 ) -> Result<impl IntoResponse, ServiceError> {
-    let feed_data_source = FeedDataSource::new(state.pool.clone());
-    let raw_feeds = feed_data_source.get_raw_feeds().await?;
-
     let duration = params.duration.unwrap_or(Duration::WEEK);
-    let max_entries = params.max_entries.unwrap_or(5);
-
-    let mut cached_feeds: Vec<CachedFeed> = Vec::new();
-    let mut new_feeds: Vec<RawFeed> = Vec::new();
-    for raw_feed in raw_feeds {
-        if let Some(cached_feed) = CacheDataSource::new(state.pool.clone())
-            .get_cached_feed(&raw_feed.name, duration, max_entries)
-            .await?
-        {
-            cached_feeds.push(cached_feed);
-        } else {
-            new_feeds.push(raw_feed)
-        }
+    let max_entries = params
+        .max_entries
+        .unwrap_or(5)
+        .clamp(1, MAX_ENTRIES_PER_FEED);
+    let unread_only = params.unread_only.unwrap_or(false);
+    let reader = user.map(|Extension(user)| user.id);
+    if unread_only && reader.is_none() {
+        return Err(ServiceError::from(ClientError::BadRequest(
+            "unread_only needs a signed-in user".to_string(),
+        )));
+    }
+    let tag_filter = tag_filter(params.tags.as_deref(), params.tag_match.unwrap_or_default());
+    if tag_filter
+        .as_ref()
+        .is_some_and(|filter| filter.tags.len() > MAX_FILTER_TAGS)
+    {
+        return Err(ServiceError::from(ClientError::BadRequest(format!(
+            "At most {} tags can be filtered on",
+            MAX_FILTER_TAGS
+        ))));
     }
 
-    let futures = new_feeds
-        .into_iter()
-        .map(|raw_feed| async move {
-            let result = XmlDataSource::get(&raw_feed.url).await;
-            (raw_feed, result)
+    // Feeds that haven't been fetched yet are left out until the refresh job
+    // caches them. Stale feeds are served as-is and revalidated in the background.
+    let load = async {
+        let cached_feeds = CacheDataSource::new(state.pool.clone())
+            .get_cached_feeds(
+                duration,
+                max_entries,
+                state.refresh.cache_duration(),
+                reader,
+                unread_only,
+                tag_filter.as_ref(),
+            )
+            .await?;
+
+        let stale = cached_feeds
+            .iter()
+            .filter(|(_, feed)| feed.freshness.stale)
+            .map(|(raw_feed_id, feed)| StaleFeed {
+                raw_feed_id: *raw_feed_id,
+                last_attempt_at: feed.freshness.last_attempt_at,
+            })
+            .collect();
+
+        Ok(FeedsBody {
+            feeds: cached_feeds.into_iter().map(|(_, feed)| feed).collect(),
+            stale,
         })
-        .collect::<Vec<_>>();
-
-    let results: Vec<(RawFeed, Result<String, anyhow::Error>)> = future::join_all(futures).await;
-
-    for (raw_feed, result) in results {
-        let xml_string = match result {
-            Ok(xml_string) => xml_string,
-            Err(e) => return Err(ServiceError::from(anyhow::Error::msg(e))),
-        };
-
-        let feed = match XmlDataSource::parse_xml_string(
-            &xml_string,
-            &raw_feed.name,
-            &raw_feed.category,
-        ) {
-            Ok(feed) => feed,
-            Err(_) => {
-                eprintln!("Failed to parse xml for feed: {}", raw_feed.name);
-                continue;
-            }
-        };
-
-        let datasource = CacheDataSource::new(state.pool.clone());
-        match datasource.cache_feed(feed.clone()).await {
-            Ok(_) => {
-                let filtered_feed = datasource
-                    .get_cached_feed(&raw_feed.name, duration, max_entries)
-                    .await?;
-                cached_feeds.push(
-                    filtered_feed
-                        .expect(format!("Cached feed '{}' not found", &raw_feed.name).as_str()),
-                );
+    };
+
+    // Read state is per user, so only anonymous responses are shared
+    let mut body = match reader {
+        Some(_) => serde_json::to_value(load.await?)?,
+        None => {
+            let mut key = format!("feeds?duration={}&max_entries={}", duration, max_entries);
+            if let Some(filter) = &tag_filter {
+                let tag_match = if filter.match_all { "all" } else { "any" };
+                key += &format!("&tags={}&tag_match={}", filter.tags.join(","), tag_match);
             }
-            Err(e) => eprintln!("Failed to get feed: {:?}", e),
-        };
+            state.responses.get_or_load(key, load).await?
+        }
+    };
+
+    let stale: Vec<StaleFeed> = serde_json::from_value(body["stale"].take())?;
+    for feed in stale {
+        state
+            .refresh
+            .revalidate(feed.raw_feed_id, feed.last_attempt_at);
     }
-    Ok(Json(cached_feeds))
+
+    Ok(Json(body["feeds"].take()))
 }
 
-pub async fn get_categories(
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, ServiceError> {
-    let categories = FeedDataSource::new(state.pool.clone())
-        .get_categories()
+pub async fn get_tags(State(state): State<AppState>) -> Result<impl IntoResponse, ServiceError> {
+    let body = state
+        .responses
+        .get_or_load(
+            "tags".to_string(),
+            FeedDataSource::new(state.pool.clone()).get_tags(),
+        )
         .await?;
 
-    Ok(Json(categories))
+    Ok(Json(body))
 }
 
 pub async fn get_raw_feeds(
@@ -125,41 +202,195 @@ pub async fn get_raw_feeds(
 
 pub async fn create_raw_feed(
     State(state): State<AppState>,
-    Json(body): Json<RawFeedInput>,
+    Extension(admin): Extension<Admin>,
+    Json(mut body): Json<RawFeedInput>,
 ) -> Result<impl IntoResponse, ServiceError> {
-    let raw_feed = FeedDataSource::new(state.pool.clone())
-        .create_raw_feed(body)
-        .await?;
+    let options = state.refresh.options();
+    options.url_policy.check(&body.url).await?;
+    let credentials = body
+        .credentials
+        .take()
+        .map(|credentials| encrypt_credentials(options, &credentials))
+        .transpose()?
+        .flatten();
+
+    let mut tx = begin_change(&state).await?;
+    let raw_feed = FeedDataSource::create_raw_feed(&mut tx, body, credentials).await?;
+    audit(
+        &mut tx,
+        &admin,
+        "feed.create",
+        Some(raw_feed.id),
+        (),
+        &raw_feed,
+    )
+    .await?;
+    commit_change(tx).await?;
+
+    state.responses.invalidate();
+    state.refresh.spawn_refresh(raw_feed.clone());
     Ok(Json(raw_feed))
 }
 
 pub async fn batch_create_raw_feeds(
     State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
     Json(body): Json<Vec<RawFeedInput>>,
 ) -> Result<impl IntoResponse, ServiceError> {
-    let raw_feeds = FeedDataSource::new(state.pool.clone())
-        .batch_create_raw_feeds(body)
-        .await?;
+    let options = state.refresh.options();
+    let mut valid_feeds = Vec::new();
+    for mut feed in body {
+        if let Err(e) = options.url_policy.check(&feed.url).await {
+            eprintln!("Skipping feed '{}': {}", feed.name, e);
+            continue;
+        }
+
+        match feed
+            .credentials
+            .take()
+            .map(|credentials| encrypt_credentials(options, &credentials))
+            .transpose()
+        {
+            Ok(credentials) => valid_feeds.push((feed, credentials.flatten())),
+            Err(e) => eprintln!("Skipping feed '{}': {}", feed.name, e),
+        }
+    }
+
+    let mut tx = begin_change(&state).await?;
+    let raw_feeds = FeedDataSource::batch_create_raw_feeds(&mut tx, valid_feeds).await?;
+    audit(&mut tx, &admin, "feed.batch_create", None, (), &raw_feeds).await?;
+    commit_change(tx).await?;
+
+    for raw_feed in raw_feeds.iter() {
+        state.refresh.spawn_refresh(raw_feed.clone());
+    }
+    state.responses.invalidate();
     Ok(Json(raw_feeds))
 }
 
 pub async fn update_raw_feed(
     State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
     Path(id): Path<i32>,
-    Json(body): Json<RawFeedInput>,
+    Json(mut body): Json<RawFeedInput>,
 ) -> Result<impl IntoResponse, ServiceError> {
-    FeedDataSource::new(state.pool.clone())
-        .update_raw_feed(id, body)
-        .await?;
+    let options = state.refresh.options();
+    options.url_policy.check(&body.url).await?;
+    let credentials = body
+        .credentials
+        .take()
+        .map(|credentials| encrypt_credentials(options, &credentials))
+        .transpose()?;
+
+    let mut tx = begin_change(&state).await?;
+    let before = FeedDataSource::fetch_raw_feed(&mut tx, id).await?;
+    FeedDataSource::update_raw_feed(&mut tx, id, body, credentials).await?;
+    let after = FeedDataSource::fetch_raw_feed(&mut tx, id).await?;
+    audit(&mut tx, &admin, "feed.update", Some(id), before, &after).await?;
+    commit_change(tx).await?;
+    state.responses.invalidate();
+
+    if let Some(raw_feed) = &after {
+        if raw_feed.is_enabled() {
+            state.refresh.spawn_refresh(raw_feed.clone());
+        }
+    }
+    Ok(())
+}
+
+pub async fn set_raw_feed_status(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<i32>,
+    Json(body): Json<FeedStatusInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let mut tx = begin_change(&state).await?;
+    let before = FeedDataSource::fetch_raw_feed(&mut tx, id).await?;
+    FeedDataSource::set_raw_feed_status(&mut tx, id, body).await?;
+    let after = FeedDataSource::fetch_raw_feed(&mut tx, id).await?;
+    audit(&mut tx, &admin, "feed.status", Some(id), before, &after).await?;
+    commit_change(tx).await?;
+    state.responses.invalidate();
+
+    // Catch up right away on a feed that was just turned back on, or once
+    // its pause is over
+    if let Some(raw_feed) = &after {
+        if raw_feed.is_enabled() {
+            state.refresh.spawn_refresh(raw_feed.clone());
+        } else {
+            state.refresh.spawn_resume(raw_feed);
+        }
+    }
     Ok(())
 }
 
 pub async fn delete_raw_feed(
     State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
     Path(id): Path<i32>,
 ) -> Result<impl IntoResponse, ServiceError> {
-    FeedDataSource::new(state.pool.clone())
-        .delete_raw_feed(id)
+    let mut tx = begin_change(&state).await?;
+    let before = FeedDataSource::fetch_raw_feed(&mut tx, id).await?;
+    FeedDataSource::delete_raw_feed(&mut tx, id).await?;
+    audit(&mut tx, &admin, "feed.delete", Some(id), before, ()).await?;
+    commit_change(tx).await?;
+    state.responses.invalidate();
+    Ok(())
+}
+
+pub async fn get_deleted_raw_feeds(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let raw_feeds = FeedDataSource::new(state.pool.clone())
+        .get_deleted_raw_feeds()
         .await?;
+
+    Ok(Json(raw_feeds))
+}
+
+pub async fn restore_raw_feed(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let mut tx = begin_change(&state).await?;
+    FeedDataSource::restore_raw_feed(&mut tx, id).await?;
+    let after = FeedDataSource::fetch_raw_feed(&mut tx, id).await?;
+    audit(&mut tx, &admin, "feed.restore", Some(id), (), &after).await?;
+    commit_change(tx).await?;
+    state.responses.invalidate();
+
+    if let Some(raw_feed) = &after {
+        if raw_feed.is_enabled() {
+            state.refresh.spawn_refresh(raw_feed.clone());
+        }
+    }
     Ok(())
 }
+
+pub async fn get_fetch_logs(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<FetchLogParam>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let fetch_logs = FetchLogDataSource::new(state.pool.clone())
+        .get_fetch_logs(id, params.limit.unwrap_or(20).clamp(1, 200))
+        .await?;
+    Ok(Json(fetch_logs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_filter() {
+        let filter = tag_filter(Some(" rust, news,,rust"), TagMatch::All).unwrap();
+        assert_eq!(filter.tags, vec!["news", "rust"]);
+        assert!(filter.match_all);
+
+        assert!(tag_filter(None, TagMatch::Any).is_none());
+        assert!(tag_filter(Some(""), TagMatch::Any).is_none());
+        assert!(tag_filter(Some(" , "), TagMatch::All).is_none());
+    }
+}
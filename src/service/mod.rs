@@ -1,5 +1,27 @@
+mod audit;
 mod cache;
+mod categories;
 mod feeds;
+mod opml;
+mod read_state;
+mod readiness;
+mod refresh;
+mod responses;
+mod retention;
+mod search;
+mod starred;
+mod subscriptions;
 
+pub use audit::*;
 pub use cache::*;
+pub use categories::*;
 pub use feeds::*;
+pub use opml::*;
+pub use read_state::*;
+pub use readiness::*;
+pub use refresh::*;
+pub use responses::*;
+pub use retention::*;
+pub use search::*;
+pub use starred::*;
+pub use subscriptions::*;
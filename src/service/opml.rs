@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use shuttle_runtime::SecretStore;
+
+use crate::{
+    data::{
+        parse_opml, redact_url, write_opml, Admin, FeedDataSource, InvalidOutline, OpmlFeed,
+        OpmlImportReport, ParseOverrides, RawFeedInput,
+    },
+    error::{is_conflict, ClientError, ServiceError},
+    service::{audit, begin_change, commit_change},
+    AppState,
+};
+
+const OPML_CONTENT_TYPE: &str = "text/x-opml; charset=utf-8";
+
+pub fn blogroll_public(secrets: &SecretStore) -> Result<bool, anyhow::Error> {
+    SecretStore::get(secrets, "OPML_BLOGROLL_PUBLIC")
+        .unwrap_or_else(|| "false".to_string())
+        .parse::<bool>()
+        .context("OPML_BLOGROLL_PUBLIC is not a valid boolean")
+}
+
+/// Every feed as it is stored, for the admin to move the list elsewhere. The
+/// public blogroll only lists enabled feeds that don't need credentials, with
+/// anything that may hold a token dropped from their URL.
+async fn opml_document(state: &AppState, public: bool) -> Result<String, anyhow::Error> {
+    let feeds: Vec<OpmlFeed> = FeedDataSource::new(state.pool.clone())
+        .get_raw_feeds()
+        .await?
+        .into_iter()
+        .filter(|raw_feed| !public || (raw_feed.is_enabled() && !raw_feed.has_credentials))
+        .map(|raw_feed| OpmlFeed {
+            name: raw_feed.name,
+            url: if public {
+                redact_url(&raw_feed.url)
+            } else {
+                raw_feed.url
+            },
+            category: raw_feed.category,
+        })
+        .collect();
+
+    Ok(write_opml("RSS Reader subscriptions", &feeds))
+}
+
+pub async fn export_opml(State(state): State<AppState>) -> Result<impl IntoResponse, ServiceError> {
+    let opml = opml_document(&state, false).await?;
+    Ok(([(header::CONTENT_TYPE, OPML_CONTENT_TYPE)], opml))
+}
+
+/// The public version of the admin export, served only when
+/// `OPML_BLOGROLL_PUBLIC` is set.
+pub async fn get_blogroll(State(state): State<AppState>) -> Result<Response, ServiceError> {
+    if !state.blogroll_public {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let body = state
+        .responses
+        .get_or_load("blogroll".to_string(), opml_document(&state, true))
+        .await?;
+    let opml = body.as_str().unwrap_or_default().to_string();
+
+    Ok(([(header::CONTENT_TYPE, OPML_CONTENT_TYPE)], opml).into_response())
+}
+
+/// Creates the feeds of an OPML subscription list. Feeds whose URL or name is
+/// already taken, or repeated in the document, are skipped, so importing the
+/// same list again creates nothing. Nested folders are flattened: a feed is
+/// filed under its innermost folder as the category.
+pub async fn import_opml(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    body: String,
+) -> Result<impl IntoResponse, ServiceError> {
+    let document = parse_opml(&body)
+        .map_err(|e| ServiceError::from(ClientError::BadRequest(format!("{:#}", e))))?;
+    let datasource = FeedDataSource::new(state.pool.clone());
+    let mut report = OpmlImportReport {
+        invalid: document.invalid,
+        ..Default::default()
+    };
+
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for feed in document.feeds {
+        if !seen.insert(feed.url.clone())
+            || datasource.get_raw_feed_by_url(&feed.url).await?.is_some()
+        {
+            report.skipped.push(feed);
+            continue;
+        }
+
+        if let Err(e) = state.refresh.options().url_policy.check(&feed.url).await {
+            report.invalid.push(InvalidOutline {
+                text: feed.name,
+                reason: e.to_string(),
+            });
+            continue;
+        }
+        candidates.push(feed);
+    }
+
+    // Each feed is created in a savepoint, so one that fails doesn't undo the
+    // others, and the whole import is audited with them
+    let mut tx = begin_change(&state).await?;
+    for feed in candidates {
+        let created = FeedDataSource::create_raw_feed(
+            &mut tx,
+            RawFeedInput {
+                name: feed.name.clone(),
+                url: feed.url.clone(),
+                category: feed.category.clone(),
+                parse_overrides: ParseOverrides::default(),
+                credentials: None,
+                tags: None,
+            },
+            None,
+        )
+        .await;
+        match created {
+            Ok(raw_feed) => report.created.push(raw_feed),
+            Err(e) if is_conflict(&e) => report.skipped.push(OpmlFeed {
+                name: feed.name,
+                url: feed.url,
+                category: feed.category,
+            }),
+            // Only errors meant for the client are worth showing, the rest
+            // would leak database details
+            Err(e) => {
+                eprintln!("Failed to import feed '{}': {:?}", feed.name, e);
+                let reason = e
+                    .chain()
+                    .find_map(|cause| cause.downcast_ref::<ClientError>())
+                    .map(|client_error| client_error.to_string())
+                    .unwrap_or_else(|| "Failed to create feed".to_string());
+                report.invalid.push(InvalidOutline {
+                    text: feed.name,
+                    reason,
+                });
+            }
+        }
+    }
+
+    if !report.created.is_empty() {
+        audit(&mut tx, &admin, "feed.import", None, (), &report.created).await?;
+    }
+    commit_change(tx).await?;
+
+    if !report.created.is_empty() {
+        state.responses.invalidate();
+
+        // A large import goes through the fetch concurrency limit as one batch
+        let refresh = state.refresh.clone();
+        let created = report.created.clone();
+        tokio::spawn(async move {
+            refresh.refresh_feeds(created).await;
+        });
+    }
+
+    Ok(Json(report))
+}
@@ -0,0 +1,29 @@
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+
+use crate::{
+    data::{ReadEntriesInput, ReadMarkerInput, ReadStateDataSource, User},
+    error::ServiceError,
+    AppState,
+};
+
+pub async fn mark_read(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<ReadMarkerInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    ReadStateDataSource::new(state.pool.clone())
+        .mark_read_until(user.id, body)
+        .await?;
+    Ok(())
+}
+
+pub async fn mark_entries_read(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<ReadEntriesInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let marked = ReadStateDataSource::new(state.pool.clone())
+        .mark_entries_read(user.id, body)
+        .await?;
+    Ok(Json(marked))
+}
@@ -0,0 +1,68 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use anyhow::Context;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use shuttle_runtime::SecretStore;
+use tokio::time::{self, Duration};
+
+use crate::{service::RefreshService, AppState};
+
+/// Reports "warming" until the startup warm-up has finished or given up.
+#[derive(Clone, Default)]
+pub struct Readiness {
+    ready: Arc<AtomicBool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReadinessStatus {
+    pub status: &'static str,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    pub fn mark_ready(&self) {
+        self.ready.store(true, Ordering::Release);
+    }
+}
+
+pub fn warm_up_deadline(secrets: &SecretStore) -> Result<Duration, anyhow::Error> {
+    let secs = SecretStore::get(secrets, "WARM_UP_DEADLINE_SECS")
+        .unwrap_or_else(|| "30".to_string())
+        .parse::<u64>()
+        .context("WARM_UP_DEADLINE_SECS is not a valid integer")?;
+
+    Ok(Duration::from_secs(secs))
+}
+
+/// Refreshes stale feeds on the scheduler's first run as leader. Fetches still
+/// running at the deadline carry on in the background, but readiness no
+/// longer waits.
+pub async fn warm_up(refresh: &RefreshService, readiness: &Readiness, deadline: Duration) {
+    println!("Warming up cache, deadline [{}] secs", deadline.as_secs());
+
+    match time::timeout(deadline, refresh.refresh_stale_feeds()).await {
+        Ok(Ok(())) => println!("Cache warm-up finished"),
+        Ok(Err(e)) => eprintln!("Cache warm-up failed: {:?}", e),
+        Err(_) => eprintln!("Cache warm-up hit its deadline"),
+    }
+
+    readiness.mark_ready();
+}
+
+pub async fn get_readiness(State(state): State<AppState>) -> impl IntoResponse {
+    if state.readiness.is_ready() {
+        (StatusCode::OK, Json(ReadinessStatus { status: "ready" }))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ReadinessStatus { status: "warming" }),
+        )
+    }
+}
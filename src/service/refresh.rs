@@ -0,0 +1,495 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::{
+    future::{BoxFuture, Shared},
+    stream, FutureExt, StreamExt,
+};
+use shuttle_runtime::SecretStore;
+use sqlx::PgPool;
+use tokio::sync::Semaphore;
+
+use super::ResponseCache;
+use crate::data::{
+    CacheDataSource, CredentialCipher, FeedDataSource, FetchError, FetchLogDataSource,
+    FetchLogInput, FetchOptions, RawFeed, UrlPolicy, XmlDataSource,
+};
+
+fn fetch_options(secrets: &SecretStore) -> Result<FetchOptions, anyhow::Error> {
+    let max_bytes = SecretStore::get(secrets, "MAX_FEED_BYTES")
+        .unwrap_or_else(|| (5 * 1024 * 1024).to_string())
+        .parse::<usize>()
+        .context("MAX_FEED_BYTES is not a valid integer")?;
+    let allow_list = SecretStore::get(secrets, "FEED_URL_ALLOW_LIST").unwrap_or_default();
+    let credential_cipher = SecretStore::get(secrets, "FEED_CREDENTIALS_KEY")
+        .map(|key| CredentialCipher::new(&key))
+        .transpose()
+        .context("FEED_CREDENTIALS_KEY is not a valid key")?;
+    // Refreshes hold the feed's lock while fetching, so a slow server must not
+    // keep it forever
+    let timeout_secs = SecretStore::get(secrets, "FEED_FETCH_TIMEOUT_SECS")
+        .unwrap_or_else(|| "30".to_string())
+        .parse::<u64>()
+        .context("FEED_FETCH_TIMEOUT_SECS is not a valid integer")?;
+    let connect_timeout_secs = SecretStore::get(secrets, "FEED_CONNECT_TIMEOUT_SECS")
+        .unwrap_or_else(|| "10".to_string())
+        .parse::<u64>()
+        .context("FEED_CONNECT_TIMEOUT_SECS is not a valid integer")?;
+
+    Ok(FetchOptions {
+        max_bytes,
+        url_policy: UrlPolicy::new(&allow_list),
+        credential_cipher,
+        timeout: Duration::from_secs(timeout_secs),
+        connect_timeout: Duration::from_secs(connect_timeout_secs),
+    })
+}
+
+type SharedRefresh = Shared<BoxFuture<'static, Result<(), Arc<anyhow::Error>>>>;
+
+/// Pool connections kept free of refreshes for request handlers.
+const RESERVED_CONNECTIONS: u32 = 2;
+
+/// Owns every outbound feed fetch. Request handlers only ever read from
+/// Postgres; the scheduler and admin changes go through here to refresh it.
+#[derive(Clone)]
+pub struct RefreshService {
+    pool: PgPool,
+    options: FetchOptions,
+    cache_duration: i32,
+    revalidate_after: TimeDelta,
+    fetch_concurrency: usize,
+    permits: Arc<Semaphore>,
+    in_flight: Arc<Mutex<HashMap<i32, SharedRefresh>>>,
+    /// When each feed was last revalidated from this process, so a stale feed
+    /// in a cached response isn't refreshed again on every request.
+    revalidated: Arc<Mutex<HashMap<i32, DateTime<Utc>>>>,
+    responses: ResponseCache,
+}
+
+impl RefreshService {
+    pub fn new(
+        pool: PgPool,
+        secrets: &SecretStore,
+        responses: ResponseCache,
+    ) -> Result<Self, anyhow::Error> {
+        let cache_duration: i32 = SecretStore::get(secrets, "CACHE_DURATION_MINS")
+            .context("Missing expected ENV_VAR: CACHE_DURATION_MINS")?
+            .parse::<i32>()
+            .context("CACHE_DURATION_MINS is not a valid integer")?;
+        let revalidate_after: i64 = SecretStore::get(secrets, "REVALIDATE_AFTER_MINS")
+            .unwrap_or_else(|| "5".to_string())
+            .parse::<i64>()
+            .context("REVALIDATE_AFTER_MINS is not a valid integer")?;
+        let fetch_concurrency: usize = SecretStore::get(secrets, "FEED_FETCH_CONCURRENCY")
+            .unwrap_or_else(|| "8".to_string())
+            .parse::<usize>()
+            .context("FEED_FETCH_CONCURRENCY is not a valid integer")?;
+
+        // Every refresh holds a connection for its whole fetch, waiting on
+        // another instance's lock included, so they never get the whole pool
+        let max_connections = pool.options().get_max_connections();
+        let refresh_connections = max_connections.saturating_sub(RESERVED_CONNECTIONS).max(1);
+        let fetch_concurrency = fetch_concurrency.clamp(1, refresh_connections as usize);
+
+        Ok(Self {
+            pool,
+            options: fetch_options(secrets)?,
+            cache_duration,
+            revalidate_after: TimeDelta::minutes(revalidate_after),
+            fetch_concurrency,
+            permits: Arc::new(Semaphore::new(fetch_concurrency)),
+            in_flight: Arc::default(),
+            revalidated: Arc::default(),
+            responses,
+        })
+    }
+
+    pub fn options(&self) -> &FetchOptions {
+        &self.options
+    }
+
+    pub fn cache_duration(&self) -> i32 {
+        self.cache_duration
+    }
+
+    pub fn responses(&self) -> &ResponseCache {
+        &self.responses
+    }
+
+    /// Refreshes a feed and invalidates the responses it changed.
+    pub async fn refresh_feed(&self, raw_feed: &RawFeed) -> Result<(), anyhow::Error> {
+        let result = self.refresh_shared(raw_feed).await;
+        // Both outcomes change what /feeds reports for this feed
+        self.responses.invalidate();
+        result
+    }
+
+    /// Refreshes a feed, joining the refresh already running for it in this
+    /// process if there is one. Runs to completion even if the caller goes away.
+    async fn refresh_shared(&self, raw_feed: &RawFeed) -> Result<(), anyhow::Error> {
+        let refresh = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(raw_feed.id)
+            .or_insert_with(|| {
+                let service = self.clone();
+                let raw_feed = raw_feed.clone();
+                let task = tokio::spawn(async move {
+                    let result = service.refresh_feed_locked(&raw_feed).await;
+                    service.in_flight.lock().unwrap().remove(&raw_feed.id);
+                    result.map_err(Arc::new)
+                });
+                async move { task.await.unwrap_or_else(|e| Err(Arc::new(e.into()))) }
+                    .boxed()
+                    .shared()
+            })
+            .clone();
+
+        refresh.await.map_err(|e| anyhow::anyhow!("{:#}", e))
+    }
+
+    /// Holds the feed's advisory lock for the whole fetch, so other instances
+    /// wait for it and reuse what it cached instead of fetching again. The
+    /// refresh only ever uses the lock's connection, and at most
+    /// `fetch_concurrency` of them run at once.
+    async fn refresh_feed_locked(&self, raw_feed: &RawFeed) -> Result<(), anyhow::Error> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .context("Refresh permits are closed")?;
+        let Some(mut lock) = CacheDataSource::new(self.pool.clone())
+            .lock_refresh(raw_feed.id)
+            .await?
+        else {
+            return Ok(());
+        };
+
+        let options = &self.options;
+        let started_at = Utc::now();
+        let timer = Instant::now();
+        let mut log = FetchLogInput {
+            feed_id: raw_feed.id,
+            started_at,
+            duration_ms: 0,
+            http_status: None,
+            bytes: None,
+            entries_parsed: None,
+            new_entries: None,
+            error: None,
+            error_kind: None,
+        };
+
+        let result: Result<(), anyhow::Error> = async {
+            let credentials = if raw_feed.has_credentials {
+                let stored = FeedDataSource::get_feed_credentials(lock.conn(), raw_feed.id)
+                    .await?
+                    .unwrap_or_default();
+                let cipher = options
+                    .credential_cipher
+                    .as_ref()
+                    .context("Missing expected ENV_VAR: FEED_CREDENTIALS_KEY")?;
+                Some(cipher.decrypt(&stored)?)
+            } else {
+                None
+            };
+
+            let response = XmlDataSource::get(&raw_feed.url, options, credentials.as_ref()).await?;
+            log.http_status = Some(response.status as i32);
+            log.bytes = Some(response.body.len() as i32);
+
+            let feed = XmlDataSource::parse_xml_string(
+                &response.body,
+                &raw_feed.name,
+                &raw_feed.category,
+                &raw_feed.parse_overrides,
+            )?;
+            log.entries_parsed = Some(feed.entries.len() as i32);
+
+            let inserted = CacheDataSource::cache_feed(lock.conn(), feed).await?;
+            log.new_entries = Some(inserted as i32);
+
+            Ok(())
+        }
+        .await;
+
+        log.duration_ms = timer.elapsed().as_millis() as i32;
+        if let Err(e) = &result {
+            eprintln!("Failed to refresh feed '{}': {:?}", raw_feed.name, e);
+            log.error = Some(format!("{:#}", e));
+            if let Some(fetch_error) = e.downcast_ref::<FetchError>() {
+                if let Some(status) = fetch_error.status() {
+                    log.http_status = Some(status as i32);
+                }
+                if let Some(bytes) = fetch_error.bytes() {
+                    log.bytes = Some(i32::try_from(bytes).unwrap_or(i32::MAX));
+                }
+                log.error_kind = Some(fetch_error.kind().to_string());
+            }
+        }
+
+        if let Some(error) = &log.error {
+            let _ =
+                CacheDataSource::record_refresh_failure(lock.conn(), &raw_feed.name, error).await;
+        }
+        let _ = FetchLogDataSource::record(lock.conn(), log).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Refreshes feeds with at most `FEED_FETCH_CONCURRENCY` fetches in flight,
+    /// capped to leave `RESERVED_CONNECTIONS` of the pool to requests. Responses
+    /// are invalidated once the whole batch is done, which happens even if the
+    /// caller goes away.
+    pub async fn refresh_feeds(
+        &self,
+        raw_feeds: Vec<RawFeed>,
+    ) -> Vec<(RawFeed, Result<(), anyhow::Error>)> {
+        let service = self.clone();
+        let batch = tokio::spawn(async move {
+            let results: Vec<_> = stream::iter(raw_feeds)
+                .map(|raw_feed| async {
+                    let result = service.refresh_shared(&raw_feed).await;
+                    (raw_feed, result)
+                })
+                .buffer_unordered(service.fetch_concurrency)
+                .collect()
+                .await;
+            service.responses.invalidate();
+            results
+        });
+
+        batch.await.unwrap_or_else(|e| {
+            eprintln!("Feed refresh batch failed: {:?}", e);
+            Vec::new()
+        })
+    }
+
+    /// Refreshes every enabled feed that is older than the cache duration or
+    /// has never been cached.
+    pub async fn refresh_stale_feeds(&self) -> Result<(), anyhow::Error> {
+        let cache = CacheDataSource::new(self.pool.clone());
+        cache.cache_clear().await.context("Failed to clear cache")?;
+
+        let stale_names = cache.get_stale_feed_names(self.cache_duration).await?;
+        let stale_feeds: Vec<RawFeed> = FeedDataSource::new(self.pool.clone())
+            .get_raw_feeds()
+            .await?
+            .into_iter()
+            .filter(|raw_feed| stale_names.contains(&raw_feed.name))
+            .collect();
+
+        if stale_feeds.is_empty() {
+            return Ok(());
+        }
+
+        let results = self.refresh_feeds(stale_feeds).await;
+        let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+        println!(
+            "Refreshed [{}] feeds, [{}] failed",
+            results.len() - failed,
+            failed
+        );
+
+        Ok(())
+    }
+
+    /// Refreshes a single feed in the background, e.g. right after it was added.
+    pub fn spawn_refresh(&self, raw_feed: RawFeed) {
+        let refresh = self.clone();
+        tokio::spawn(async move {
+            let _ = refresh.refresh_feed(&raw_feed).await;
+        });
+    }
+
+    /// Schedules a background refresh for a stale feed that is being served,
+    /// unless it was already attempted recently, here or on another instance.
+    pub fn revalidate(&self, raw_feed_id: i32, last_attempt_at: Option<DateTime<Utc>>) {
+        let now = Utc::now();
+        {
+            let mut revalidated = self.revalidated.lock().unwrap();
+            let last_attempt_at = revalidated.get(&raw_feed_id).copied().max(last_attempt_at);
+            if last_attempt_at.is_some_and(|attempt| attempt > now - self.revalidate_after) {
+                return;
+            }
+            revalidated.insert(raw_feed_id, now);
+        }
+
+        let refresh = self.clone();
+        tokio::spawn(async move {
+            let raw_feed = FeedDataSource::new(refresh.pool.clone())
+                .get_raw_feed(raw_feed_id)
+                .await;
+            if let Ok(Some(raw_feed)) = raw_feed {
+                if raw_feed.is_enabled() {
+                    let _ = refresh.refresh_feed(&raw_feed).await;
+                }
+            }
+        });
+    }
+
+    /// Refreshes a paused feed as soon as its pause is over, which also
+    /// invalidates responses, unless the pause was changed in the meantime.
+    pub fn spawn_resume(&self, raw_feed: &RawFeed) {
+        let now = Utc::now();
+        let Some(until) = raw_feed.paused_until.filter(|until| *until > now) else {
+            return;
+        };
+        if !raw_feed.active || raw_feed.deleted_at.is_some() {
+            return;
+        }
+
+        let refresh = self.clone();
+        let raw_feed_id = raw_feed.id;
+        tokio::spawn(async move {
+            tokio::time::sleep((until - now).to_std().unwrap_or_default()).await;
+            let raw_feed = FeedDataSource::new(refresh.pool.clone())
+                .get_raw_feed(raw_feed_id)
+                .await;
+            if let Ok(Some(raw_feed)) = raw_feed {
+                if raw_feed.is_enabled() && raw_feed.paused_until == Some(until) {
+                    let _ = refresh.refresh_feed(&raw_feed).await;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{
+        CachedFeed, Duration, FeedServer, ParseOverrides, RawFeedInput, ScratchDatabase,
+    };
+
+    use super::*;
+
+    fn secrets() -> SecretStore {
+        serde_json::from_value(serde_json::json!({
+            "CACHE_DURATION_MINS": "30",
+            "FEED_URL_ALLOW_LIST": "127.0.0.1",
+        }))
+        .unwrap()
+    }
+
+    fn refresh_service(pool: &PgPool) -> RefreshService {
+        let secrets = secrets();
+        let responses = ResponseCache::new(pool.clone(), &secrets).unwrap();
+        RefreshService::new(pool.clone(), &secrets, responses).unwrap()
+    }
+
+    async fn create_feed(pool: &PgPool, url: &str) -> RawFeed {
+        let mut conn = pool.acquire().await.unwrap();
+        FeedDataSource::create_raw_feed(
+            &mut conn,
+            RawFeedInput {
+                name: "feed".to_string(),
+                url: url.to_string(),
+                category: "news".to_string(),
+                parse_overrides: ParseOverrides::default(),
+                credentials: None,
+                tags: None,
+            },
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn cached_feeds(pool: &PgPool) -> Vec<(i32, CachedFeed)> {
+        CacheDataSource::new(pool.clone())
+            .get_cached_feeds(Duration::WEEK, 5, 30, None, false, None)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_refresh_feeds_fetches_each_feed_once() {
+        let database = ScratchDatabase::new("test_refresh_feeds_fetches_once").await;
+        let server = FeedServer::start().await;
+        let feed = create_feed(&database.pool, &server.url).await;
+        let refresh = refresh_service(&database.pool);
+        refresh
+            .responses()
+            .get_or_load("feeds".to_string(), async { Ok(Vec::<i32>::new()) })
+            .await
+            .unwrap();
+
+        // The second refresh of the same feed joins the one in flight
+        let results = refresh
+            .refresh_feeds(vec![feed.clone(), feed.clone()])
+            .await;
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(server.hits(), 1);
+
+        let cached = cached_feeds(&database.pool).await;
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].0, feed.id);
+        assert_eq!(cached[0].1.entries.len(), 2);
+        assert!(!cached[0].1.freshness.stale);
+
+        let logs = FetchLogDataSource::new(database.pool.clone())
+            .get_fetch_logs(feed.id, 10)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].http_status, Some(200));
+        assert_eq!(logs[0].new_entries, Some(2));
+
+        assert_eq!(refresh.responses().stats().entries, 0);
+
+        database.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_stale_feed_is_served_and_revalidated_once() {
+        let database = ScratchDatabase::new("test_stale_feed_revalidated_once").await;
+        let server = FeedServer::start().await;
+        let feed = create_feed(&database.pool, &server.url).await;
+        let refresh = refresh_service(&database.pool);
+        refresh.refresh_feed(&feed).await.unwrap();
+
+        sqlx::query(
+            "UPDATE cached_feeds
+            SET last_refreshed_at = NOW() - INTERVAL '1 hour',
+                last_attempt_at = NOW() - INTERVAL '1 hour'",
+        )
+        .execute(&database.pool)
+        .await
+        .unwrap();
+
+        // Stale feeds are still served with their entries
+        let cached = cached_feeds(&database.pool).await;
+        assert!(cached[0].1.freshness.stale);
+        assert_eq!(cached[0].1.entries.len(), 2);
+
+        // Attempted recently by another instance, so left alone
+        refresh.revalidate(feed.id, Some(Utc::now()));
+        let last_attempt_at = cached[0].1.freshness.last_attempt_at;
+        refresh.revalidate(feed.id, last_attempt_at);
+        refresh.revalidate(feed.id, last_attempt_at);
+        server.wait_for_hits(2).await;
+
+        let mut fresh = false;
+        for _ in 0..100 {
+            fresh = !cached_feeds(&database.pool).await[0].1.freshness.stale;
+            if fresh {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(fresh);
+        assert_eq!(server.hits(), 2);
+
+        database.drop().await;
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::Serialize;
+use serde_json::Value;
+use shuttle_runtime::SecretStore;
+use sqlx::PgPool;
+use tokio::time;
+
+use crate::{data::NotifyDataSource, error::ServiceError, AppState};
+
+struct CachedResponse {
+    body: Value,
+    stored_at: Instant,
+}
+
+#[derive(Default)]
+struct Responses {
+    entries: Mutex<HashMap<String, CachedResponse>>,
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub max_entries: usize,
+    pub ttl_secs: u64,
+}
+
+/// Keeps the JSON bodies of the public read endpoints in memory, keyed by
+/// their normalized query. Anything that changes cached data invalidates it,
+/// here and, through Postgres notifications, on every other instance.
+/// Callers choose the keys, so at most `max_entries` are kept: expired ones
+/// are evicted first, then the oldest.
+#[derive(Clone)]
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entries: usize,
+    responses: Arc<Responses>,
+    pool: Option<PgPool>,
+}
+
+impl ResponseCache {
+    pub fn new(pool: PgPool, secrets: &SecretStore) -> Result<Self, anyhow::Error> {
+        let ttl_secs = SecretStore::get(secrets, "RESPONSE_CACHE_TTL_SECS")
+            .unwrap_or_else(|| "60".to_string())
+            .parse::<u64>()
+            .context("RESPONSE_CACHE_TTL_SECS is not a valid integer")?;
+        let max_entries = SecretStore::get(secrets, "RESPONSE_CACHE_MAX_ENTRIES")
+            .unwrap_or_else(|| "1000".to_string())
+            .parse::<usize>()
+            .context("RESPONSE_CACHE_MAX_ENTRIES is not a valid integer")?;
+
+        Ok(Self {
+            ttl: Duration::from_secs(ttl_secs),
+            max_entries,
+            responses: Arc::default(),
+            pool: Some(pool),
+        })
+    }
+
+    /// Returns the cached body for `key`, or loads and caches it. A load that
+    /// raced with an invalidation is served but not kept.
+    pub async fn get_or_load<T, F>(&self, key: String, load: F) -> Result<Value, anyhow::Error>
+    where
+        T: Serialize,
+        F: Future<Output = Result<T, anyhow::Error>>,
+    {
+        if let Some(cached) = self.responses.entries.lock().unwrap().get(&key) {
+            if cached.stored_at.elapsed() < self.ttl {
+                self.responses.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(cached.body.clone());
+            }
+        }
+        self.responses.misses.fetch_add(1, Ordering::Relaxed);
+
+        let generation = self.responses.generation.load(Ordering::Acquire);
+        let body = serde_json::to_value(load.await?)?;
+
+        let mut entries = self.responses.entries.lock().unwrap();
+        if self.responses.generation.load(Ordering::Acquire) == generation && self.max_entries > 0 {
+            if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+                self.evict(&mut entries);
+            }
+            entries.insert(
+                key,
+                CachedResponse {
+                    body: body.clone(),
+                    stored_at: Instant::now(),
+                },
+            );
+        }
+
+        Ok(body)
+    }
+
+    fn evict(&self, entries: &mut HashMap<String, CachedResponse>) {
+        entries.retain(|_, cached| cached.stored_at.elapsed() < self.ttl);
+        if entries.len() < self.max_entries {
+            return;
+        }
+
+        let oldest = entries
+            .iter()
+            .min_by_key(|(_, cached)| cached.stored_at)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            entries.remove(&oldest);
+        }
+    }
+
+    pub fn invalidate(&self) {
+        self.clear();
+
+        if let Some(pool) = self.pool.clone() {
+            tokio::spawn(async move {
+                let _ = NotifyDataSource::new(pool).notify_invalidation().await;
+            });
+        }
+    }
+
+    fn clear(&self) {
+        let mut entries = self.responses.entries.lock().unwrap();
+        self.responses.generation.fetch_add(1, Ordering::AcqRel);
+        entries.clear();
+    }
+
+    pub fn stats(&self) -> ResponseCacheStats {
+        ResponseCacheStats {
+            hits: self.responses.hits.load(Ordering::Relaxed),
+            misses: self.responses.misses.load(Ordering::Relaxed),
+            entries: self.responses.entries.lock().unwrap().len(),
+            max_entries: self.max_entries,
+            ttl_secs: self.ttl.as_secs(),
+        }
+    }
+}
+
+/// Drops local responses whenever any instance, this one included, invalidates.
+pub async fn listen_for_invalidations(
+    pool: PgPool,
+    responses: ResponseCache,
+) -> Result<(), anyhow::Error> {
+    let mut listener = NotifyDataSource::new(pool).listen_invalidations().await?;
+
+    loop {
+        match listener.try_recv().await {
+            // `None` means the connection dropped and notifications may have
+            // been missed while it was down; the next call reconnects.
+            Ok(_) => responses.clear(),
+            Err(e) => {
+                eprintln!("Failed to receive cache invalidation: {:?}", e);
+                time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+pub async fn get_response_cache_stats(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ServiceError> {
+    Ok(Json(state.responses.stats()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::ScratchDatabase;
+
+    fn cache(ttl_secs: u64) -> ResponseCache {
+        ResponseCache {
+            ttl: Duration::from_secs(ttl_secs),
+            max_entries: 2,
+            responses: Arc::default(),
+            pool: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hit_after_miss() {
+        let cache = cache(60);
+        let first = cache
+            .get_or_load("feeds".to_string(), async { Ok(vec![1]) })
+            .await
+            .unwrap();
+        let second = cache
+            .get_or_load("feeds".to_string(), async { Ok(vec![2]) })
+            .await
+            .unwrap();
+
+        assert_eq!(first, second);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_drops_entries() {
+        let cache = cache(60);
+        cache
+            .get_or_load("feeds".to_string(), async { Ok(vec![1]) })
+            .await
+            .unwrap();
+        cache.invalidate();
+        let body = cache
+            .get_or_load("feeds".to_string(), async { Ok(vec![2]) })
+            .await
+            .unwrap();
+
+        assert_eq!(body, serde_json::json!([2]));
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_load_racing_invalidation_is_not_kept() {
+        let cache = cache(60);
+        let racing = cache.clone();
+        cache
+            .get_or_load("feeds".to_string(), async move {
+                racing.invalidate();
+                Ok(vec![1])
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_entry_is_reloaded() {
+        let cache = cache(0);
+        for _ in 0..2 {
+            cache
+                .get_or_load("feeds".to_string(), async { Ok(vec![1]) })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_oldest_entry_evicted_when_full() {
+        let cache = cache(60);
+        for key in ["a", "b", "c"] {
+            cache
+                .get_or_load(key.to_string(), async { Ok(vec![1]) })
+                .await
+                .unwrap();
+            time::sleep(Duration::from_millis(2)).await;
+        }
+
+        let entries = cache.responses.entries.lock().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries.contains_key("a"));
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_evicted_first() {
+        let cache = ResponseCache {
+            ttl: Duration::ZERO,
+            ..cache(60)
+        };
+        for key in ["a", "b", "c"] {
+            cache
+                .get_or_load(key.to_string(), async { Ok(vec![1]) })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(cache.stats().entries, 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch Postgres database"]
+    async fn test_invalidation_reaches_other_instances() {
+        let database = ScratchDatabase::new("test_invalidation_reaches_others").await;
+        let secrets: SecretStore = serde_json::from_value(serde_json::json!({})).unwrap();
+        let here = ResponseCache::new(database.pool.clone(), &secrets).unwrap();
+        let there = ResponseCache::new(database.pool.clone(), &secrets).unwrap();
+        there
+            .get_or_load("feeds".to_string(), async { Ok(vec![1]) })
+            .await
+            .unwrap();
+        let listener = tokio::spawn(listen_for_invalidations(
+            database.pool.clone(),
+            there.clone(),
+        ));
+
+        // Notifications sent before the listener is up are lost, so keep
+        // invalidating until one gets through
+        for _ in 0..100 {
+            here.invalidate();
+            time::sleep(Duration::from_millis(50)).await;
+            if there.stats().entries == 0 {
+                break;
+            }
+        }
+        assert_eq!(there.stats().entries, 0);
+
+        listener.abort();
+        let _ = listener.await;
+        database.drop().await;
+    }
+}
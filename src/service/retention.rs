@@ -0,0 +1,89 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use shuttle_runtime::SecretStore;
+
+use crate::{
+    data::{Admin, RetentionDataSource, RetentionRuleInput},
+    error::ServiceError,
+    service::{audit, begin_change, commit_change},
+    AppState,
+};
+
+pub fn retention_batch_size(secrets: &SecretStore) -> Result<i64, anyhow::Error> {
+    let batch_size = SecretStore::get(secrets, "RETENTION_BATCH_SIZE")
+        .unwrap_or_else(|| "500".to_string())
+        .parse::<i64>()
+        .context("RETENTION_BATCH_SIZE is not a valid integer")?;
+    if batch_size < 1 {
+        anyhow::bail!("RETENTION_BATCH_SIZE must be at least 1");
+    }
+    Ok(batch_size)
+}
+
+pub async fn get_retention_rules(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let rules = RetentionDataSource::new(state.pool.clone())
+        .get_retention_rules()
+        .await?;
+    Ok(Json(rules))
+}
+
+pub async fn set_retention_rule(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    Json(body): Json<RetentionRuleInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let after = serde_json::to_value(&body)?;
+    let mut tx = begin_change(&state).await?;
+    RetentionDataSource::set_retention_rule(&mut tx, body).await?;
+    audit(&mut tx, &admin, "retention.set", None, (), after).await?;
+    commit_change(tx).await?;
+    Ok(())
+}
+
+pub async fn delete_retention_rule(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let mut tx = begin_change(&state).await?;
+    let before = RetentionDataSource::fetch_retention_rules(&mut tx)
+        .await?
+        .into_iter()
+        .find(|rule| rule.id == id);
+    RetentionDataSource::delete_retention_rule(&mut tx, id).await?;
+    audit(&mut tx, &admin, "retention.delete", Some(id), before, ()).await?;
+    commit_change(tx).await?;
+    Ok(())
+}
+
+pub async fn prune_retention(
+    State(state): State<AppState>,
+    Extension(admin): Extension<Admin>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let report = RetentionDataSource::new(state.pool.clone())
+        .prune_entries(retention_batch_size(&state.secrets)?)
+        .await?;
+    if report.entries_removed > 0 {
+        state.responses.invalidate();
+    }
+
+    // Pruning commits batch by batch to keep locks short, so its entry can
+    // only be written once it is done. Failing the request then would only
+    // hide the report of what was removed.
+    let audited: Result<(), anyhow::Error> = async {
+        let mut tx = begin_change(&state).await?;
+        audit(&mut tx, &admin, "retention.prune", None, (), &report).await?;
+        commit_change(tx).await
+    }
+    .await;
+    if let Err(e) = audited {
+        eprintln!("Retention prune went unaudited: {:?}", e);
+    }
+    Ok(Json(report))
+}
@@ -0,0 +1,25 @@
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+
+use crate::{
+    data::{SearchDataSource, SearchQuery, SearchResult},
+    error::ServiceError,
+    AppState,
+};
+
+pub async fn search_entries(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> Result<impl IntoResponse, ServiceError> {
+    if params.q.trim().is_empty() {
+        return Ok(Json(Vec::<SearchResult>::new()));
+    }
+
+    let results = SearchDataSource::new(state.pool.clone())
+        .search(&params)
+        .await?;
+    Ok(Json(results))
+}
@@ -0,0 +1,39 @@
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+
+use crate::{
+    data::{StarInput, StarredDataSource, User},
+    error::ServiceError,
+    AppState,
+};
+
+pub async fn get_starred(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let starred = StarredDataSource::new(state.pool.clone())
+        .get_starred(user.id)
+        .await?;
+    Ok(Json(starred))
+}
+
+pub async fn star_entry(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<StarInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    StarredDataSource::new(state.pool.clone())
+        .star(user.id, body)
+        .await?;
+    Ok(())
+}
+
+pub async fn unstar_entry(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<StarInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    StarredDataSource::new(state.pool.clone())
+        .unstar(user.id, body)
+        .await?;
+    Ok(())
+}
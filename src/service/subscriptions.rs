@@ -0,0 +1,142 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{
+    auth::as_admin,
+    data::{
+        FeedDataSource, ParseOverrides, RawFeed, RawFeedInput, SubscriptionDataSource,
+        SubscriptionInput, SubscriptionUpdateInput, User,
+    },
+    error::{ClientError, ServiceError},
+    service::{audit, begin_change, commit_change},
+    AppState,
+};
+
+pub async fn get_catalog(State(state): State<AppState>) -> Result<impl IntoResponse, ServiceError> {
+    let catalog = SubscriptionDataSource::new(state.pool.clone())
+        .get_catalog()
+        .await?;
+    Ok(Json(catalog))
+}
+
+pub async fn get_subscriptions(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let subscriptions = SubscriptionDataSource::new(state.pool.clone())
+        .get_subscriptions(user.id)
+        .await?;
+    Ok(Json(subscriptions))
+}
+
+pub async fn subscribe(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<SubscriptionInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let feeds = FeedDataSource::new(state.pool.clone());
+    let raw_feed = match (body.feed_id, body.url) {
+        (Some(feed_id), _) => feeds
+            .get_raw_feed(feed_id)
+            .await?
+            .ok_or_else(|| ClientError::NotFound(format!("Unknown feed: {}", feed_id)))?,
+        (None, Some(url)) => {
+            let url = url.trim().to_string();
+            catalog_feed(&state, &user, &feeds, url, body.name, &body.category).await?
+        }
+        (None, None) => {
+            return Err(ServiceError::from(ClientError::BadRequest(
+                "A feed_id or url is needed to subscribe".to_string(),
+            )))
+        }
+    };
+
+    let subscription = SubscriptionDataSource::new(state.pool.clone())
+        .subscribe(user.id, raw_feed.id, &body.category)
+        .await?;
+    Ok(Json(subscription))
+}
+
+/// Feeds are shared by URL: a URL already in the catalog is reused as it is.
+/// Only the admin can add a new one, which is then fetched once for everyone.
+async fn catalog_feed(
+    state: &AppState,
+    user: &User,
+    feeds: &FeedDataSource,
+    url: String,
+    name: Option<String>,
+    category: &str,
+) -> Result<RawFeed, ServiceError> {
+    if let Some(raw_feed) = feeds.get_raw_feed_by_url(&url).await? {
+        return Ok(raw_feed);
+    }
+
+    let Some(admin) = as_admin(&state.secrets, user.github_id)? else {
+        return Err(ServiceError::from(ClientError::Forbidden(format!(
+            "Feed is not in the catalog, ask the admin to add it: {}",
+            url
+        ))));
+    };
+    let name = name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| {
+            ClientError::BadRequest("A name is needed to add a feed to the catalog".to_string())
+        })?;
+
+    state.refresh.options().url_policy.check(&url).await?;
+    let mut tx = begin_change(state).await?;
+    let raw_feed = FeedDataSource::create_raw_feed(
+        &mut tx,
+        RawFeedInput {
+            name,
+            url,
+            category: category.to_string(),
+            parse_overrides: ParseOverrides::default(),
+            credentials: None,
+            tags: None,
+        },
+        None,
+    )
+    .await?;
+    audit(
+        &mut tx,
+        &admin,
+        "feed.create",
+        Some(raw_feed.id),
+        (),
+        &raw_feed,
+    )
+    .await?;
+    commit_change(tx).await?;
+
+    state.responses.invalidate();
+    state.refresh.spawn_refresh(raw_feed.clone());
+    Ok(raw_feed)
+}
+
+pub async fn update_subscription(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<i32>,
+    Json(body): Json<SubscriptionUpdateInput>,
+) -> Result<impl IntoResponse, ServiceError> {
+    SubscriptionDataSource::new(state.pool.clone())
+        .update_subscription(user.id, id, body)
+        .await?;
+    Ok(())
+}
+
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServiceError> {
+    SubscriptionDataSource::new(state.pool.clone())
+        .unsubscribe(user.id, id)
+        .await?;
+    Ok(())
+}